@@ -16,7 +16,9 @@ fn pos_vel_iter_synco(c: &mut Criterion) {
 
     impl Component for Vel {}
 
-    impl Component for Sticky {}
+    impl Component for Sticky {
+        type Storage = NullStorage<Self>;
+    }
 
     let mut ecs = Ecs::new()
         .with_storage::<Pos>()
@@ -48,6 +50,39 @@ fn pos_vel_iter_synco(c: &mut Criterion) {
     });
 }
 
+fn pos_vel_iter_synco_archetype(c: &mut Criterion) {
+    use synco::*;
+
+    let mut ecs = Ecs::new_with_layout(Layout::Archetype)
+        .with_storage::<Pos>()
+        .with_storage::<Vel>()
+        .with_storage::<Sticky>();
+
+    for _ in 0..ENTITIES {
+        ecs.create()
+            .with(Pos([1, 2]))
+            .with(Vel([3, 4]))
+            .finish();
+    }
+
+    c.bench_function("pos_vel_iter_synco_archetype", |b| {
+        for pos in ecs.query::<&mut Pos>().iter() {
+            pos.0 = [0, 0];
+        }
+
+        b.iter(|| {
+            for _ in 0..ITER {
+                for (pos, vel) in ecs.query::<(&mut Pos, &Vel)>().iter() {
+                    pos.0[0] += vel.0[0];
+                    pos.0[1] += vel.0[1];
+
+                    black_box((pos, vel));
+                }
+            }
+        });
+    });
+}
+
 fn pos_vel_iter_specs(c: &mut Criterion) {
     use specs::prelude::*;
 
@@ -113,5 +148,73 @@ fn pos_vel_iter_specs(c: &mut Criterion) {
     });
 }
 
-criterion_group!(compare, pos_vel_iter_synco, pos_vel_iter_specs);
-criterion_main!(compare);
+fn spawn_despawn_synco(c: &mut Criterion) {
+    use synco::*;
+
+    let mut ecs = Ecs::new()
+        .with_storage::<Pos>()
+        .with_storage::<Vel>()
+        .with_storage::<Sticky>();
+
+    // Pre-populate the world so that slot lookup cost is visible
+    for _ in 0..ENTITIES {
+        ecs.create()
+            .with(Pos([1, 2]))
+            .finish();
+    }
+
+    let mut entities = Vec::with_capacity(ENTITIES);
+
+    c.bench_function("spawn_despawn_synco", |b| {
+        b.iter(|| {
+            for _ in 0..ENTITIES {
+                entities.push(ecs.create()
+                    .with(Pos([1, 2]))
+                    .with(Vel([3, 4]))
+                    .finish());
+            }
+
+            for entity in entities.drain(..) {
+                ecs.delete(black_box(entity));
+            }
+        });
+    });
+}
+
+fn spawn_despawn_specs(c: &mut Criterion) {
+    use specs::prelude::*;
+
+    let mut ecs = World::new();
+
+    ecs.register::<Pos>();
+    ecs.register::<Vel>();
+
+    for _ in 0..ENTITIES {
+        ecs.create_entity()
+            .with(Pos([1, 2]))
+            .build();
+    }
+
+    let mut entities = Vec::with_capacity(ENTITIES);
+
+    c.bench_function("spawn_despawn_specs", |b| {
+        b.iter(|| {
+            for _ in 0..ENTITIES {
+                entities.push(ecs.create_entity()
+                    .with(Pos([1, 2]))
+                    .with(Vel([3, 4]))
+                    .build());
+            }
+
+            for entity in entities.drain(..) {
+                ecs.delete_entity(black_box(entity)).unwrap();
+            }
+
+            ecs.maintain();
+        });
+    });
+}
+
+criterion_group!(compare, pos_vel_iter_synco, pos_vel_iter_synco_archetype, pos_vel_iter_specs);
+criterion_group!(churn, spawn_despawn_synco, spawn_despawn_specs);
+criterion_main!(compare, churn);
@@ -0,0 +1,300 @@
+use super::*;
+
+use std::cell::UnsafeCell;
+
+/// How an [`Ecs`] stores its components, chosen with [`Ecs::new_with_layout`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Each component type lives in its own [`Component::Storage`].
+    #[default]
+    Storage,
+    /// Entities with the same set of components are grouped into an archetype, and each component type is stored as
+    /// one contiguous column per archetype. Queries iterate archetype by archetype, which is faster for patterns that
+    /// touch many components, at the cost of moving an entity's components whenever it gains or loses one.
+    ///
+    /// [`Component::Storage`] is still created for each component, but is left empty.
+    Archetype,
+}
+
+/// Where an entity's components live in a [`Layout::Archetype`] world.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    pub(crate) archetype: usize,
+    pub(crate) row: usize,
+}
+
+impl Location {
+    /// The index of the entity's archetype.
+    pub fn archetype(&self) -> usize { self.archetype }
+
+    /// The index of the entity's row within each column of its archetype.
+    pub fn row(&self) -> usize { self.row }
+}
+
+struct Archetype {
+    mask: BitMask,
+    // The entity in each row
+    entities: Vec<EntityId>,
+}
+
+/// The archetypes of a [`Layout::Archetype`] world, and the location of each entity within them.
+pub struct Archetypes {
+    // Archetype 0 has no components, and doesn't keep track of its entities
+    archetypes: Vec<Archetype>,
+    by_mask: HashMap<BitMask, usize>,
+    // By entity index
+    locations: Vec<Location>,
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        Self {
+            archetypes: vec![Archetype { mask: BitMask::zero(), entities: Vec::new() }],
+            by_mask: std::iter::once((BitMask::zero(), 0)).collect(),
+            locations: Vec::new(),
+        }
+    }
+}
+
+impl Archetypes {
+    /// The entity's current location. Entities without components are in archetype 0.
+    pub(crate) fn location(&self, entity: EntityId) -> Location {
+        self.locations.get(entity.idx()).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn mask(&self, archetype: usize) -> &BitMask { &self.archetypes[archetype].mask }
+
+    pub(crate) fn entities(&self, archetype: usize) -> &[EntityId] { &self.archetypes[archetype].entities }
+
+    /// The archetypes whose entities match the filter.
+    pub(crate) fn matching<'a>(&'a self, filter: &'a Filter) -> impl Iterator<Item = usize> + 'a {
+        self.archetypes
+            .iter()
+            .enumerate()
+            .filter(move |(_, archetype)| archetype.mask.matches(filter))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Find the archetype with the given components, creating it if it doesn't exist yet.
+    pub(crate) fn get_or_insert(&mut self, mask: &BitMask) -> usize {
+        if let Some(archetype) = self.by_mask.get(mask) {
+            return *archetype;
+        }
+        let archetype = self.archetypes.len();
+        self.archetypes.push(Archetype { mask: mask.clone(), entities: Vec::new() });
+        self.by_mask.insert(mask.clone(), archetype);
+        archetype
+    }
+
+    /// Move the entity to the end of another archetype, returning its new location.
+    ///
+    /// The last entity of the old archetype takes the entity's row, so columns must be moved with `swap_remove`.
+    pub(crate) fn move_entity(&mut self, entity: EntityId, from: Location, to: usize) -> Location {
+        if from.archetype != 0 {
+            let entities = &mut self.archetypes[from.archetype].entities;
+            entities.swap_remove(from.row);
+            if let Some(moved) = entities.get(from.row) {
+                self.locations[moved.idx()].row = from.row;
+            }
+        }
+
+        let row = if to != 0 {
+            let entities = &mut self.archetypes[to].entities;
+            entities.push(entity);
+            entities.len() - 1
+        } else {
+            0
+        };
+
+        let location = Location { archetype: to, row };
+        if self.locations.len() <= entity.idx() {
+            self.locations.resize(entity.idx() + 1, Location::default());
+        }
+        self.locations[entity.idx()] = location;
+        location
+    }
+}
+
+/// The columns of component `C` in a [`Layout::Archetype`] world, one per archetype.
+pub struct Table<C> {
+    // Queries borrow components mutably through a shared reference to the table, see `Table::cells`
+    columns: Vec<Vec<UnsafeCell<C>>>,
+}
+
+// Safety: components are only mutated through a shared reference by queries, which never alias their outputs
+unsafe impl<C: Send + Sync> Sync for Table<C> {}
+
+impl<C> Default for Table<C> {
+    fn default() -> Self { Self { columns: Vec::new() } }
+}
+
+impl<C> Table<C> {
+    fn column_mut(&mut self, archetype: usize) -> &mut Vec<UnsafeCell<C>> {
+        if self.columns.len() <= archetype {
+            self.columns.resize_with(archetype + 1, Vec::new);
+        }
+        &mut self.columns[archetype]
+    }
+
+    /// The number of components in all columns.
+    pub fn len(&self) -> usize { self.columns.iter().map(Vec::len).sum() }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The column of an archetype, in the same order as the archetype's entities.
+    pub fn column(&self, archetype: usize) -> &[C] {
+        let cells = self.cells(archetype);
+        // Safety: `UnsafeCell<C>` has the same layout as `C`, and no query can mutate the cells while the table is
+        // borrowed outside of it
+        unsafe { std::slice::from_raw_parts(cells.as_ptr() as *const C, cells.len()) }
+    }
+
+    /// The column of an archetype, through which a query may mutate the components of distinct rows.
+    pub(crate) fn cells(&self, archetype: usize) -> &[UnsafeCell<C>] {
+        self.columns.get(archetype).map_or(&[], Vec::as_slice)
+    }
+
+    /// Safety: The location must hold a component.
+    pub(crate) unsafe fn get(&self, location: Location) -> &C {
+        &*self.columns.get_unchecked(location.archetype).get_unchecked(location.row).get()
+    }
+
+    /// Safety: The location must hold a component.
+    pub(crate) unsafe fn get_mut(&mut self, location: Location) -> &mut C {
+        self.columns.get_unchecked_mut(location.archetype).get_unchecked_mut(location.row).get_mut()
+    }
+
+    pub(crate) fn iter_mut(&mut self, archetype: usize) -> impl Iterator<Item = &mut C> {
+        self.column_mut(archetype).iter_mut().map(UnsafeCell::get_mut)
+    }
+
+    /// Safety: The component must belong in the archetype's next row.
+    pub(crate) unsafe fn push(&mut self, archetype: usize, comp: C) {
+        self.column_mut(archetype).push(UnsafeCell::new(comp));
+    }
+
+    /// Safety: The location must hold a component.
+    pub(crate) unsafe fn swap_remove(&mut self, location: Location) -> C {
+        self.column_mut(location.archetype).swap_remove(location.row).into_inner()
+    }
+
+    /// Move a component to the next row of another archetype.
+    ///
+    /// Safety: The location must hold a component, and it must belong in the archetype's next row.
+    pub(crate) unsafe fn relocate(&mut self, from: Location, to: usize) {
+        let comp = self.swap_remove(from);
+        self.push(to, comp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each component holds the entity it belongs to, so that misaligned columns can be spotted
+    #[derive(Clone)]
+    struct A(EntityId);
+    impl Component for A {}
+
+    #[derive(Clone)]
+    struct B(EntityId);
+    impl Component for B {}
+
+    // Check that every column is in the same order as its archetype's entities, and that locations agree with both
+    fn check(ecs: &Ecs) {
+        let archetypes = ecs.read_resource::<Archetypes>();
+        let entities = ecs.entities();
+        let (a_table, b_table) = (ecs.read_resource::<Table<A>>(), ecs.read_resource::<Table<B>>());
+        let (a_id, b_id) = (ecs.storage_id::<A>(), ecs.storage_id::<B>());
+
+        for (archetype, Archetype { mask, entities: rows }) in archetypes.archetypes.iter().enumerate().skip(1) {
+            let owners = |has: bool, owners: Vec<EntityId>| if has {
+                assert_eq!(&owners, rows);
+            } else {
+                assert!(owners.is_empty());
+            };
+            owners(mask.bit_is_set(a_id), a_table.column(archetype).iter().map(|a| a.0).collect());
+            owners(mask.bit_is_set(b_id), b_table.column(archetype).iter().map(|b| b.0).collect());
+
+            for (row, entity) in rows.iter().enumerate() {
+                assert_eq!(archetypes.location(*entity), Location { archetype, row });
+                assert_eq!(entities.comp_mask(*entity), Some(mask));
+            }
+        }
+
+        // Entities with components are in exactly one row
+        let rows = archetypes.archetypes.iter().map(|archetype| archetype.entities.len()).sum::<usize>();
+        assert_eq!(rows, ecs.query::<AnyOf<(&A, &B)>>().iter().count());
+    }
+
+    #[test]
+    fn move_entity() {
+        let mut entities = Entities::default();
+        let es = (0..3).map(|_| entities.create()).collect::<Vec<_>>();
+
+        let mut archetypes = Archetypes::default();
+        let from = archetypes.get_or_insert(&BitMask::with(0));
+        let to = archetypes.get_or_insert(&BitMask::with(1));
+        assert_eq!(archetypes.get_or_insert(&BitMask::with(0)), from);
+        for &entity in &es {
+            archetypes.move_entity(entity, archetypes.location(entity), from);
+        }
+
+        // The last entity takes the row of the one that left
+        let location = archetypes.move_entity(es[0], archetypes.location(es[0]), to);
+        assert_eq!(location, Location { archetype: to, row: 0 });
+        assert_eq!(archetypes.entities(from), &[es[2], es[1]]);
+        assert_eq!(archetypes.location(es[2]), Location { archetype: from, row: 0 });
+
+        // Removing the last row leaves the others alone, and archetype 0 doesn't track its entities
+        assert_eq!(archetypes.move_entity(es[1], archetypes.location(es[1]), 0), Location::default());
+        assert_eq!(archetypes.entities(from), &[es[2]]);
+        assert!(archetypes.entities(0).is_empty());
+        assert_eq!(archetypes.location(es[2]), Location { archetype: from, row: 0 });
+    }
+
+    #[test]
+    fn columns_follow_entities() {
+        let mut ecs = Ecs::new_with_layout(Layout::Archetype)
+            .with_cloneable_storage::<A>()
+            .with_cloneable_storage::<B>();
+        let es = (0..8).map(|_| ecs.create().finish()).collect::<Vec<_>>();
+
+        for &entity in &es {
+            ecs.insert_comp(entity, A(entity));
+            check(&ecs);
+        }
+        for &entity in es.iter().step_by(2) {
+            ecs.insert_comp(entity, B(entity));
+            check(&ecs);
+        }
+
+        // Replacing a component leaves the entity where it is
+        let location = ecs.read_resource::<Archetypes>().location(es[2]);
+        ecs.insert_comp(es[2], A(es[2]));
+        assert_eq!(ecs.read_resource::<Archetypes>().location(es[2]), location);
+        check(&ecs);
+
+        ecs.remove_comp::<A>(es[0]);
+        check(&ecs);
+        ecs.remove_comp::<A>(es[1]);
+        check(&ecs);
+        ecs.delete(es[4]);
+        check(&ecs);
+        ecs.insert_bundle(es[1], (A(es[1]), B(es[1])));
+        check(&ecs);
+        ecs.remove_bundle::<(A, B)>(es[6]);
+        check(&ecs);
+
+        let clone = ecs.clone_entity(es[2]);
+        ecs.insert_bundle(clone, (A(clone), B(clone)));
+        check(&ecs);
+
+        ecs.clear_comps::<B>();
+        check(&ecs);
+        for &entity in &es {
+            ecs.delete(entity);
+            check(&ecs);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::ops::Range;
+
+const LAYERS: usize = 4;
+const WORD_BITS: usize = u64::BITS as usize;
+const WORD_SHIFT: usize = 6;
+
+/// A set of entity indices, with a hierarchy of summary layers above it so that empty regions can be skipped.
+///
+/// Each bit of a layer is set when the corresponding word of the layer below is non-zero.
+#[derive(Default)]
+pub(crate) struct HiBitSet {
+    // `layers[0]` holds a bit per index
+    layers: [Vec<u64>; LAYERS],
+}
+
+impl HiBitSet {
+    pub fn insert(&mut self, idx: usize) {
+        for layer in 0..LAYERS {
+            let (word, bit) = Self::locate(idx, layer);
+            let words = &mut self.layers[layer];
+            if words.len() <= word {
+                words.resize(word + 1, 0);
+            }
+            let was_empty = words[word] == 0;
+            words[word] |= 1 << bit;
+            // The layers above already know about this word
+            if !was_empty {
+                break;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, idx: usize) {
+        for layer in 0..LAYERS {
+            let (word, bit) = Self::locate(idx, layer);
+            match self.layers[layer].get_mut(word) {
+                Some(w) => {
+                    *w &= !(1 << bit);
+                    // The layers above still need to know about this word
+                    if *w != 0 {
+                        break;
+                    }
+                },
+                None => break,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, idx: usize) -> bool {
+        let (word, bit) = Self::locate(idx, 0);
+        self.word(0, word) & (1 << bit) != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.iter_mut().for_each(Vec::clear);
+    }
+
+    /// Visit the indices in this set, in ascending order.
+    pub fn iter(&self) -> SetIter<'_> { SetIter::union(vec![self]) }
+
+    // The word and bit of the given layer that summarize the index
+    fn locate(idx: usize, layer: usize) -> (usize, usize) {
+        let idx = idx >> (WORD_SHIFT * layer);
+        (idx >> WORD_SHIFT, idx % WORD_BITS)
+    }
+
+    fn word(&self, layer: usize, word: usize) -> u64 {
+        self.layers[layer].get(word).copied().unwrap_or(0)
+    }
+}
+
+/// Iterates over the indices present in all (or any) of a number of sets, in ascending order.
+pub(crate) struct SetIter<'a> {
+    sets: Vec<&'a HiBitSet>,
+    union: bool,
+    // Only indices within this range are visited
+    range: Range<usize>,
+    // The next word of the top layer to visit
+    top: usize,
+    top_len: usize,
+    // The bits of the current word of each layer that are yet to be visited
+    bits: [u64; LAYERS],
+    // The index of the current word of each layer
+    words: [usize; LAYERS],
+}
+
+impl<'a> SetIter<'a> {
+    /// Visit the indices present in all of the sets.
+    pub fn intersection(sets: Vec<&'a HiBitSet>) -> Self {
+        let top_len = sets
+            .iter()
+            .map(|set| set.layers[LAYERS - 1].len())
+            .min()
+            .unwrap_or(0);
+        Self { sets, union: false, range: 0..usize::MAX, top: 0, top_len, bits: [0; LAYERS], words: [0; LAYERS] }
+    }
+
+    /// Visit the indices present in any of the sets.
+    pub fn union(sets: Vec<&'a HiBitSet>) -> Self {
+        let top_len = sets
+            .iter()
+            .map(|set| set.layers[LAYERS - 1].len())
+            .max()
+            .unwrap_or(0);
+        Self { sets, union: true, range: 0..usize::MAX, top: 0, top_len, bits: [0; LAYERS], words: [0; LAYERS] }
+    }
+
+    /// Only visit the indices within the range, so that disjoint ranges of the sets can be visited in parallel.
+    pub fn within(mut self, range: Range<usize>) -> Self {
+        let top_shift = WORD_SHIFT * LAYERS;
+        self.top = range.start >> top_shift;
+        self.top_len = if range.is_empty() { 0 } else { self.top_len.min(((range.end - 1) >> top_shift) + 1) };
+        self.range = range;
+        self
+    }
+
+    fn word(&self, layer: usize, word: usize) -> u64 {
+        let bits = if self.union {
+            self.sets
+                .iter()
+                .fold(0, |bits, set| bits | set.word(layer, word))
+        } else {
+            self.sets
+                .iter()
+                .fold(!0, |bits, set| bits & set.word(layer, word))
+        };
+        bits & self.mask(layer, word)
+    }
+
+    // The bits of a word of the layer that summarize indices within the range
+    fn mask(&self, layer: usize, word: usize) -> u64 {
+        let shift = WORD_SHIFT * layer;
+        // The first index summarized by the word
+        let base = (word << WORD_SHIFT) << shift;
+        let first = self.range.start.saturating_sub(base) >> shift;
+        let end = self.range.end.saturating_sub(base);
+        let last = (end >> shift) + (end & ((1 << shift) - 1) != 0) as usize;
+        match (first, last.min(WORD_BITS)) {
+            (first, last) if first >= last => 0,
+            (first, WORD_BITS) => !0 << first,
+            (first, last) => (!0 << first) & ((1 << last) - 1),
+        }
+    }
+}
+
+impl<'a> Iterator for SetIter<'a> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        // Fast path: more indices remain in the current word of the bottom layer
+        if self.bits[0] != 0 {
+            let bit = self.bits[0].trailing_zeros() as usize;
+            self.bits[0] &= self.bits[0] - 1;
+            return Some((self.words[0] << WORD_SHIFT) | bit);
+        }
+
+        loop {
+            match (0..LAYERS).find(|layer| self.bits[*layer] != 0) {
+                Some(layer) => {
+                    let bit = self.bits[layer].trailing_zeros() as usize;
+                    self.bits[layer] &= self.bits[layer] - 1;
+                    let idx = (self.words[layer] << WORD_SHIFT) | bit;
+                    if layer == 0 {
+                        break Some(idx);
+                    } else {
+                        // Descend into the word of the layer below
+                        self.bits[layer - 1] = self.word(layer - 1, idx);
+                        self.words[layer - 1] = idx;
+                    }
+                },
+                None if self.top < self.top_len => {
+                    self.bits[LAYERS - 1] = self.word(LAYERS - 1, self.top);
+                    self.words[LAYERS - 1] = self.top;
+                    self.top += 1;
+                },
+                None => break None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    // Indices either side of the words of each layer
+    const BOUNDARIES: &[usize] = &[0, 63, 64, 4095, 4096, 262143, 262144];
+
+    fn check(set: &HiBitSet, model: &BTreeSet<usize>) {
+        assert_eq!(set.iter().collect::<Vec<_>>(), model.iter().copied().collect::<Vec<_>>());
+        // Each bit of a layer is set exactly when the corresponding word of the layer below is non-zero
+        for layer in 1..LAYERS {
+            for (word, bits) in set.layers[layer].iter().enumerate() {
+                for bit in 0..WORD_BITS {
+                    let below = (word << WORD_SHIFT) | bit;
+                    assert_eq!(bits & (1 << bit) != 0, set.word(layer - 1, below) != 0, "layer {}, word {}", layer, word);
+                }
+            }
+        }
+    }
+
+    fn is_empty(set: &HiBitSet) -> bool { set.layers.iter().flatten().all(|word| *word == 0) }
+
+    // A xorshift generator, so that the tests are repeatable
+    fn rng(mut state: u64) -> impl FnMut() -> u64 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    #[test]
+    fn boundaries() {
+        for &idx in BOUNDARIES {
+            let mut set = HiBitSet::default();
+            set.insert(idx);
+            assert!(set.contains(idx));
+            assert!(!set.contains(idx + 1));
+            assert!(idx == 0 || !set.contains(idx - 1));
+            check(&set, &std::iter::once(idx).collect());
+
+            set.remove(idx);
+            assert!(!set.contains(idx));
+            assert!(is_empty(&set), "{} left bits behind", idx);
+        }
+
+        let mut set = HiBitSet::default();
+        let mut model = BTreeSet::new();
+        for &idx in BOUNDARIES {
+            set.insert(idx);
+            model.insert(idx);
+            check(&set, &model);
+        }
+        for &idx in BOUNDARIES.iter().rev() {
+            set.remove(idx);
+            model.remove(&idx);
+            check(&set, &model);
+        }
+        assert!(is_empty(&set));
+
+        // Removing indices beyond the end of the set does nothing
+        set.remove(1 << 24);
+        assert!(is_empty(&set));
+    }
+
+    #[test]
+    fn remove_clears_upper_layers() {
+        let mut set = HiBitSet::default();
+        for &idx in &[4095, 4096, 262143] {
+            set.insert(idx);
+        }
+
+        // 4096 is alone in its words of the bottom two layers, but shares the third with 4095
+        set.remove(4096);
+        assert_eq!(set.word(0, 4096 >> WORD_SHIFT), 0);
+        assert_eq!(set.word(1, 4096 >> (2 * WORD_SHIFT)), 0);
+        assert_ne!(set.word(2, 0), 0);
+        check(&set, &[4095, 262143].iter().copied().collect());
+
+        // 262143 is alone in its words of the bottom two layers, so only its bit of the third is cleared
+        set.remove(262143);
+        assert_eq!(set.word(2, 0), 1);
+        check(&set, &std::iter::once(4095).collect());
+
+        set.remove(4095);
+        assert!(is_empty(&set));
+    }
+
+    #[test]
+    fn set_ops_match_model() {
+        let mut next = rng(0x2545_f491_4f6c_dd1d);
+        let models = (0..4)
+            .map(|_| (0..500)
+                .map(|_| match next() % 3 {
+                    // Cluster around the boundaries so that words are shared between sets
+                    0 => BOUNDARIES[next() as usize % BOUNDARIES.len()] + (next() % 8) as usize,
+                    1 => (next() % 8192) as usize,
+                    _ => (next() % 300_000) as usize,
+                })
+                .collect::<BTreeSet<_>>())
+            .collect::<Vec<_>>();
+        let sets = models
+            .iter()
+            .map(|model| {
+                let mut set = HiBitSet::default();
+                model.iter().for_each(|idx| set.insert(*idx));
+                set
+            })
+            .collect::<Vec<_>>();
+
+        // Every combination of the sets, including none of them
+        for combination in 0..1 << sets.len() {
+            let members = (0..sets.len()).filter(|i| combination & (1 << i) != 0).collect::<Vec<_>>();
+            let of = |i: &usize| &sets[*i];
+
+            let union = members.iter().flat_map(|i| &models[*i]).copied().collect::<BTreeSet<_>>();
+            let intersection = union
+                .iter()
+                .copied()
+                .filter(|idx| !members.is_empty() && members.iter().all(|i| models[*i].contains(idx)))
+                .collect::<Vec<_>>();
+
+            assert_eq!(SetIter::union(members.iter().map(of).collect()).collect::<Vec<_>>(), union.into_iter().collect::<Vec<_>>());
+            assert_eq!(SetIter::intersection(members.iter().map(of).collect()).collect::<Vec<_>>(), intersection);
+        }
+    }
+
+    #[test]
+    fn within_matches_model() {
+        let mut next = rng(0x9e37_79b9_7f4a_7c15);
+        let model = (0..2000)
+            .map(|_| match next() % 2 {
+                0 => BOUNDARIES[next() as usize % BOUNDARIES.len()] + (next() % 8) as usize,
+                _ => (next() % 300_000) as usize,
+            })
+            .collect::<BTreeSet<_>>();
+        let mut set = HiBitSet::default();
+        model.iter().for_each(|idx| set.insert(*idx));
+
+        let mut ends = BOUNDARIES.iter().flat_map(|idx| [*idx, idx + 1]).collect::<Vec<_>>();
+        ends.extend((0..20).map(|_| (next() % 300_000) as usize));
+        ends.push(usize::MAX);
+        for &start in &ends {
+            for &end in &ends {
+                let expected = model.iter().copied().filter(|idx| (start..end).contains(idx)).collect::<Vec<_>>();
+                assert_eq!(SetIter::union(vec![&set]).within(start..end).collect::<Vec<_>>(), expected, "{}..{}", start, end);
+                assert_eq!(SetIter::intersection(vec![&set, &set]).within(start..end).collect::<Vec<_>>(), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use super::{*, change::{ComponentTicks, RemovalLog}};
+
+/// A group of components that can be inserted into or removed from an entity at once.
+pub trait Bundle: Sized + 'static {
+    /// The mask of the components in this bundle.
+    fn comp_mask(ecs: &Ecs) -> Result<BitMask, Error>;
+
+    /// Call [`Component::on_insert`] for each component in the bundle that is in the mask.
+    fn on_insert(ecs: &mut Ecs, entity: EntityId, mask: &BitMask);
+
+    /// Call [`Component::on_replace`] for each component in the bundle that is in the mask.
+    fn on_replace(ecs: &mut Ecs, entity: EntityId, mask: &BitMask);
+
+    /// Call [`Component::on_remove`] for each component in the bundle that is in the mask.
+    fn on_remove(ecs: &mut Ecs, entity: EntityId, mask: &BitMask);
+
+    /// Safety: The entity must be alive, its component mask must already include the bundle and it must have been moved
+    /// to its new archetype. `old_mask` must contain exactly the components of the bundle that the entity had before
+    /// its component mask was updated.
+    unsafe fn insert_unchecked(self, ecs: &mut Ecs, entity: EntityId, old_mask: &BitMask, tick: u64);
+
+    /// Safety: The entity must be alive, its component mask must already exclude the bundle and it must not have been
+    /// moved to its new archetype yet. `old_mask` must contain exactly the components of the bundle that the entity had
+    /// before its component mask was updated.
+    unsafe fn remove_unchecked(ecs: &mut Ecs, entity: EntityId, old_mask: &BitMask, tick: u64);
+}
+
+fn add_comp<C: Component>(ecs: &Ecs, mut mask: BitMask) -> Result<BitMask, Error> {
+    let comp_id = ecs.try_storage_id::<C>()?;
+    if mask.bit_is_set(comp_id) {
+        return Err(Error::DuplicateComponent(type_name::<C>()));
+    }
+    mask.set_bit(comp_id);
+    Ok(mask)
+}
+
+unsafe fn insert_comp<C: Component>(ecs: &mut Ecs, entity: EntityId, comp: C, old_mask: &BitMask, tick: u64) {
+    let replaced = old_mask.bit_is_set(ecs.storage_id::<C>());
+    drop(ecs.put_comp(entity, comp, replaced));
+    ecs.mut_resource::<ComponentTicks<C>>().insert(entity, tick, replaced);
+}
+
+unsafe fn remove_comp<C: Component>(ecs: &mut Ecs, entity: EntityId, old_mask: &BitMask, tick: u64) {
+    if old_mask.bit_is_set(ecs.storage_id::<C>()) {
+        drop(ecs.take_comp::<C>(entity));
+        ecs.mut_resource::<RemovalLog<C>>().push(tick, entity);
+    }
+}
+
+macro_rules! impl_for_tuple {
+    ($($x:ident),*) => {
+        impl<$($x: Component),*> Bundle for ($($x,)*) {
+            fn comp_mask(ecs: &Ecs) -> Result<BitMask, Error> {
+                let mask = BitMask::zero();
+                $(let mask = add_comp::<$x>(ecs, mask)?;)*
+                Ok(mask)
+            }
+
+            fn on_insert(ecs: &mut Ecs, entity: EntityId, mask: &BitMask) {
+                $(if mask.bit_is_set(ecs.storage_id::<$x>()) { $x::on_insert(ecs, entity); })*
+            }
+
+            fn on_replace(ecs: &mut Ecs, entity: EntityId, mask: &BitMask) {
+                $(if mask.bit_is_set(ecs.storage_id::<$x>()) { $x::on_replace(ecs, entity); })*
+            }
+
+            fn on_remove(ecs: &mut Ecs, entity: EntityId, mask: &BitMask) {
+                $(if mask.bit_is_set(ecs.storage_id::<$x>()) { $x::on_remove(ecs, entity); })*
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn insert_unchecked(self, ecs: &mut Ecs, entity: EntityId, old_mask: &BitMask, tick: u64) {
+                let ($($x,)*) = self;
+                $(insert_comp::<$x>(ecs, entity, $x, old_mask, tick);)*
+            }
+
+            unsafe fn remove_unchecked(ecs: &mut Ecs, entity: EntityId, old_mask: &BitMask, tick: u64) {
+                $(remove_comp::<$x>(ecs, entity, old_mask, tick);)*
+            }
+        }
+    };
+}
+
+impl_for_tuple!();
+impl_for_tuple!(A);
+impl_for_tuple!(A, B);
+impl_for_tuple!(A, B, C);
+impl_for_tuple!(A, B, C, D);
+impl_for_tuple!(A, B, C, D, E);
+impl_for_tuple!(A, B, C, D, E, F);
+impl_for_tuple!(A, B, C, D, E, F, G);
+impl_for_tuple!(A, B, C, D, E, F, G, H);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P);
+impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q);
@@ -0,0 +1,187 @@
+use super::*;
+
+use std::{
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The ticks that a system or query observes changes relative to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Ticks {
+    /// Changes made after this tick are considered new.
+    pub last_run: u64,
+    /// The tick at which changes made through this fetch are recorded.
+    pub this_run: u64,
+}
+
+#[derive(Default)]
+struct Tick {
+    added: AtomicU64,
+    changed: AtomicU64,
+}
+
+/// The ticks at which each entity's component was last added and changed.
+pub struct ComponentTicks<C> {
+    ticks: Vec<Tick>,
+    phantom: PhantomData<C>,
+}
+
+impl<C> Default for ComponentTicks<C> {
+    fn default() -> Self { Self { ticks: Vec::new(), phantom: PhantomData } }
+}
+
+impl<C> ComponentTicks<C> {
+    pub(crate) fn insert(&mut self, entity: EntityId, tick: u64, replaced: bool) {
+        let idx = entity.idx();
+        self.ticks.resize_with(self.ticks.len().max(idx + 1), Tick::default);
+        let ticks = &mut self.ticks[idx];
+        if !replaced {
+            *ticks.added.get_mut() = tick;
+        }
+        *ticks.changed.get_mut() = tick;
+    }
+
+    /// Safety: The entity must have an already-inserted component.
+    pub(crate) unsafe fn mark_changed(&self, entity: EntityId, tick: u64) {
+        self.ticks.get_unchecked(entity.idx()).changed.store(tick, Ordering::Relaxed);
+    }
+
+    /// Safety: The entity must have an already-inserted component.
+    pub unsafe fn added(&self, entity: EntityId) -> u64 {
+        self.ticks.get_unchecked(entity.idx()).added.load(Ordering::Relaxed)
+    }
+
+    /// Safety: The entity must have an already-inserted component.
+    pub unsafe fn changed(&self, entity: EntityId) -> u64 {
+        self.ticks.get_unchecked(entity.idx()).changed.load(Ordering::Relaxed)
+    }
+}
+
+/// Matches entities whose component `C` was inserted since the system last ran.
+pub struct Added<C: Component>(PhantomData<C>);
+
+impl<C: Component> Pattern for Added<C> {
+    type State<'a> = (Read<'a, ComponentTicks<C>>, u64);
+    type Output<'a> = ();
+    type Columns<'a> = ();
+
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+        Ok(Filter::with(ecs.try_storage_id::<C>()?))
+    }
+
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+        Ok((ecs.try_read_resource()?, ticks.last_run))
+    }
+
+    fn access(access: &mut Access) { access.read::<ComponentTicks<C>>() }
+
+    unsafe fn matches<'b>((ticks, last_run): &Self::State<'b>, entity: EntityId) -> bool {
+        ticks.added(entity) > *last_run
+    }
+
+    unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
+
+    fn columns<'a, 'b: 'a>(state: &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {}
+
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, columns: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {}
+}
+
+impl<C: Component> ParPattern for Added<C> {
+    unsafe fn get_shared<'a, 'b: 'a>(state: &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
+}
+
+/// Matches entities whose component `C` was inserted or mutably accessed since the system last ran.
+pub struct Changed<C: Component>(PhantomData<C>);
+
+impl<C: Component> Pattern for Changed<C> {
+    type State<'a> = (Read<'a, ComponentTicks<C>>, u64);
+    type Output<'a> = ();
+    type Columns<'a> = ();
+
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+        Ok(Filter::with(ecs.try_storage_id::<C>()?))
+    }
+
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+        Ok((ecs.try_read_resource()?, ticks.last_run))
+    }
+
+    fn access(access: &mut Access) { access.read::<ComponentTicks<C>>() }
+
+    unsafe fn matches<'b>((ticks, last_run): &Self::State<'b>, entity: EntityId) -> bool {
+        ticks.changed(entity) > *last_run
+    }
+
+    unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
+
+    fn columns<'a, 'b: 'a>(state: &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {}
+
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, columns: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {}
+}
+
+impl<C: Component> ParPattern for Changed<C> {
+    unsafe fn get_shared<'a, 'b: 'a>(state: &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
+}
+
+/// A log of the entities that have had component `C` removed.
+pub struct RemovalLog<C> {
+    removed: Vec<(u64, EntityId)>,
+    // Removals at or before this tick are pruned by the next call to `Ecs::maintain`
+    last_maintain: u64,
+    phantom: PhantomData<C>,
+}
+
+impl<C> Default for RemovalLog<C> {
+    fn default() -> Self { Self { removed: Vec::new(), last_maintain: 0, phantom: PhantomData } }
+}
+
+impl<C> RemovalLog<C> {
+    pub(crate) fn push(&mut self, tick: u64, entity: EntityId) {
+        self.removed.push((tick, entity));
+    }
+
+    /// Drop removals at or before the tick.
+    pub(crate) fn prune(&mut self, tick: u64) {
+        // Removals are logged in tick order
+        let pruned = self.removed.iter().take_while(|(removed, _)| *removed <= tick).count();
+        self.removed.drain(..pruned);
+    }
+
+    pub(crate) fn maintain(&mut self, tick: u64) {
+        let last_maintain = self.last_maintain;
+        self.removed.retain(|(removed, _)| *removed > last_maintain);
+        self.last_maintain = tick;
+    }
+}
+
+/// A system input that yields the entities that have had component `C` removed since the system last ran, either by
+/// [`Ecs::remove_comp`] or by deleting the entity.
+///
+/// Removals are pruned after each system runs, once every live [`SystemState`] has seen them, so a system that runs
+/// for the first time may miss older removals. Calling [`Ecs::maintain`] also prunes removals made before the
+/// previous call, so that systems which stop running don't keep them alive forever.
+pub struct RemovedComponents<'a, C: Component> {
+    log: Read<'a, RemovalLog<C>>,
+    last_run: u64,
+}
+
+impl<'a, C: Component> RemovedComponents<'a, C> {
+    pub fn iter(&self) -> impl Iterator<Item = EntityId> + '_ {
+        let last_run = self.last_run;
+        self.log.removed
+            .iter()
+            .filter(move |(removed, _)| *removed > last_run)
+            .map(|(_, entity)| *entity)
+    }
+}
+
+impl<'a, C: Component> Input<'a> for RemovedComponents<'a, C> {
+    fn try_fetch(ecs: &'a Ecs, ticks: Ticks) -> Result<Self, Error> {
+        Ok(RemovedComponents {
+            log: ecs.try_read_resource()?,
+            last_run: ticks.last_run,
+        })
+    }
+
+    fn access(access: &mut Access) { access.read::<RemovalLog<C>>() }
+}
@@ -0,0 +1,72 @@
+use super::*;
+
+/// A deferred operation on the ECS.
+pub trait Command: Shareable + 'static {
+    fn apply(self: Box<Self>, ecs: &mut Ecs);
+}
+
+impl<F: FnOnce(&mut Ecs) + Shareable + 'static> Command for F {
+    fn apply(self: Box<Self>, ecs: &mut Ecs) { (*self)(ecs) }
+}
+
+/// Structural changes recorded by [`Commands`], waiting to be applied with [`Ecs::apply_commands`].
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl CommandQueue {
+    pub(crate) fn take(&mut self) -> Vec<Box<dyn Command>> {
+        core::mem::take(&mut self.commands)
+    }
+}
+
+/// A system input that defers structural changes to the ECS until [`Ecs::apply_commands`] is called.
+///
+/// Commands targeting an entity that no longer exists by the time they are applied are ignored.
+pub struct Commands<'a> {
+    entities: Read<'a, Entities>,
+    queue: Write<'a, CommandQueue>,
+}
+
+impl<'a> Commands<'a> {
+    /// Reserve a new entity. Components may be inserted into it immediately using [`Commands::insert_comp`].
+    pub fn create(&mut self) -> EntityId {
+        self.entities.reserve()
+    }
+
+    pub fn delete(&mut self, entity: EntityId) {
+        self.add(move |ecs: &mut Ecs| ecs.delete(entity));
+    }
+
+    pub fn insert_comp<C: Component>(&mut self, entity: EntityId, comp: C) {
+        self.add(move |ecs: &mut Ecs| if ecs.entities_mut().entry(entity).is_some() {
+            ecs.insert_comp(entity, comp);
+        });
+    }
+
+    pub fn remove_comp<C: Component>(&mut self, entity: EntityId) {
+        self.add(move |ecs: &mut Ecs| if ecs.entities_mut().entry(entity).is_some() {
+            ecs.remove_comp::<C>(entity);
+        });
+    }
+
+    /// Record an arbitrary operation on the ECS.
+    pub fn add<C: Command>(&mut self, command: C) {
+        self.queue.commands.push(Box::new(command));
+    }
+}
+
+impl<'a> Input<'a> for Commands<'a> {
+    fn try_fetch(ecs: &'a Ecs, ticks: Ticks) -> Result<Self, Error> {
+        Ok(Commands {
+            entities: ecs.try_read_resource()?,
+            queue: ecs.try_write_resource()?,
+        })
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<Entities>();
+        access.write::<CommandQueue>();
+    }
+}
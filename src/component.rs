@@ -1,9 +1,48 @@
-use super::*;
+use super::{*, archetype::{Archetypes, Location, Table}, change::RemovalLog};
 
 use std::marker::PhantomData;
 
-pub trait Component: Sized + Any {
+pub trait Component: Sized + Any + Shareable {
     type Storage: Storage<Self> = VecStorage<Self>;
+
+    /// Called after the component is inserted into an entity that did not already have one.
+    fn on_insert(ecs: &mut Ecs, entity: EntityId) {}
+
+    /// Called before the component is replaced by [`Ecs::insert_comp`], while the old component is still present.
+    fn on_replace(ecs: &mut Ecs, entity: EntityId) {}
+
+    /// Called before the component is removed, either by [`Ecs::remove_comp`] or by deleting the entity, while it is
+    /// still present.
+    fn on_remove(ecs: &mut Ecs, entity: EntityId) {}
+}
+
+/// A zero-sized marker component, stored in a [`NullStorage`].
+///
+/// `T` only distinguishes tags from one another and is never stored, so any type may be used.
+pub struct Tag<T>(PhantomData<fn() -> T>);
+
+impl<T> Tag<T> {
+    pub fn new() -> Self { Self(PhantomData) }
+}
+
+impl<T> Default for Tag<T> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T> Clone for Tag<T> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T> Copy for Tag<T> {}
+
+impl<T> fmt::Debug for Tag<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tag<{}>", type_name::<T>())
+    }
+}
+
+impl<T: 'static> Component for Tag<T> {
+    type Storage = NullStorage<Self>;
 }
 
 pub(crate) struct ComponentId<C: Component> {
@@ -16,3 +55,63 @@ impl<C: Component> ComponentId<C> {
         Self { id, phantom: PhantomData }
     }
 }
+
+/// Type-erased operations on a component's storage, indexed by component id.
+pub(crate) struct ComponentVTable {
+    pub name: &'static str,
+    /// Remove the entity's component (if any) and drop it.
+    pub drop: fn(&mut Ecs, EntityId),
+    /// Clone the component (if any) of the first entity onto the second. `None` if the component is not `Clone`.
+    pub clone: Option<fn(&mut Ecs, EntityId, EntityId)>,
+    /// Count the number of entities that have this component, failing if the storage is borrowed mutably.
+    pub len: fn(&Ecs) -> Result<usize, Error>,
+    /// Move the component at a location to the next row of another archetype. Only used by [`Layout::Archetype`].
+    pub relocate: fn(&mut Ecs, Location, usize),
+    /// Prune old entries from the removal log.
+    pub maintain: fn(&mut Ecs, u64),
+    /// Prune entries at or before the tick from the removal log, unless it is borrowed.
+    pub prune: fn(&Ecs, u64),
+}
+
+impl ComponentVTable {
+    pub fn new<C: Component>() -> Self {
+        Self {
+            name: type_name::<C>(),
+            drop: |ecs, entity| { ecs.remove_comp::<C>(entity); },
+            clone: None,
+            len: |ecs| Ok(match ecs.layout() {
+                Layout::Storage => ecs.try_read_resource::<ComponentStorage<C>>()?.len(),
+                Layout::Archetype => ecs.try_read_resource::<Table<C>>()?.len(),
+            }),
+            // Safety: the caller moves the entity's row in the same way
+            relocate: |ecs, from, to| unsafe { ecs.mut_resource::<Table<C>>().relocate(from, to) },
+            maintain: |ecs, tick| ecs.mut_resource::<RemovalLog<C>>().maintain(tick),
+            prune: |ecs, tick| if let Ok(mut log) = ecs.try_write_resource::<RemovalLog<C>>() {
+                log.prune(tick);
+            },
+        }
+    }
+
+    pub fn new_cloneable<C: Component + Clone>() -> Self {
+        Self {
+            clone: Some(|ecs, src, dst| {
+                let comp_id = ecs.storage_id::<C>();
+                let has_comp = ecs.entities_mut()
+                    .comp_mask(src)
+                    .map_or(false, |mask| mask.bit_is_set(comp_id));
+                if has_comp {
+                    // Safety: the component mask has been checked above
+                    let comp = match ecs.layout() {
+                        Layout::Storage => C::clone(unsafe { &*ecs.read_resource::<ComponentStorage<C>>().get_unchecked(src) }),
+                        Layout::Archetype => {
+                            let location = ecs.read_resource::<Archetypes>().location(src);
+                            C::clone(unsafe { ecs.read_resource::<Table<C>>().get(location) })
+                        },
+                    };
+                    ecs.insert_comp(dst, comp);
+                }
+            }),
+            ..Self::new::<C>()
+        }
+    }
+}
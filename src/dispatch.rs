@@ -0,0 +1,98 @@
+use super::{*, system::Helper};
+
+trait RunSystem: Shareable {
+    fn run(&self, ecs: &Ecs, state: &SystemState);
+}
+
+impl<S> RunSystem for S where S: for<'a> System<'a> + Clone + Shareable + 'static {
+    fn run(&self, ecs: &Ecs, state: &SystemState) {
+        ecs.run_with_state::<_, Helper>(state, self.clone());
+    }
+}
+
+struct Entry {
+    name: &'static str,
+    access: Access,
+    system: Box<dyn RunSystem>,
+    state: SystemState,
+}
+
+/// Runs many systems, grouping them into stages of systems with non-conflicting inputs.
+///
+/// With the `parallel` feature enabled, the systems within each stage run concurrently. Systems that conflict always
+/// run in the order they were added.
+#[derive(Default)]
+pub struct Dispatcher {
+    systems: Vec<Entry>,
+    stages: Vec<Vec<usize>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn add<S>(&mut self, system: S)
+        where S: for<'a> System<'a> + Clone + Shareable + 'static
+    {
+        let access = Access::of_input::<<S as System<'static>>::Input>();
+        if let Some(conflict) = access.self_conflict() {
+            panic!("System `{}` has conflicting borrows of `{}`", type_name::<S>(), conflict);
+        }
+
+        // Run after the last stage that contains a conflicting system
+        let stage = self.stages
+            .iter()
+            .rposition(|stage| stage
+                .iter()
+                .any(|idx| self.systems[*idx].access.conflict_with(&access).is_some()))
+            .map_or(0, |stage| stage + 1);
+
+        if stage == self.stages.len() {
+            self.stages.push(Vec::new());
+        }
+        self.stages[stage].push(self.systems.len());
+
+        self.systems.push(Entry {
+            name: type_name::<S>(),
+            access,
+            system: Box::new(system),
+            state: SystemState::default(),
+        });
+    }
+
+    pub fn with<S>(mut self, system: S) -> Self
+        where S: for<'a> System<'a> + Clone + Shareable + 'static
+    {
+        self.add(system);
+        self
+    }
+
+    /// The names of the systems in each stage, in the order that stages are run.
+    pub fn stages(&self) -> impl Iterator<Item = Vec<&'static str>> + '_ {
+        self.stages
+            .iter()
+            .map(move |stage| stage
+                .iter()
+                .map(|idx| self.systems[*idx].name)
+                .collect())
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn run(&self, ecs: &Ecs) {
+        for stage in &self.stages {
+            rayon::scope(|s| for idx in stage {
+                let entry = &self.systems[*idx];
+                s.spawn(move |_| entry.system.run(ecs, &entry.state));
+            });
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    pub fn run(&self, ecs: &Ecs) {
+        for stage in &self.stages {
+            for idx in stage {
+                let entry = &self.systems[*idx];
+                entry.system.run(ecs, &entry.state);
+            }
+        }
+    }
+}
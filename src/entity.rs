@@ -1,11 +1,13 @@
-use super::*;
+use super::{*, bitset::{HiBitSet, SetIter}};
 
 use std::{
     convert::TryInto,
-    ops::BitOrAssign,
+    hash::{Hash, Hasher},
+    ops::{BitOrAssign, Range},
+    sync::atomic::{AtomicIsize, Ordering},
 };
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct EntityId {
     idx: u32,
     gen: u32,
@@ -16,6 +18,10 @@ impl EntityId {
         Self { idx: 0, gen: 0 }
     }
 
+    pub(crate) fn from_raw(idx: usize, gen: u32) -> Self {
+        Self { idx: idx as u32, gen }
+    }
+
     pub(crate) fn idx(&self) -> usize { self.idx as usize }
     pub(crate) fn gen(&self) -> usize { self.gen as usize }
 }
@@ -30,21 +36,27 @@ pub(crate) struct Entry {
 #[derive(Default)]
 pub struct Entities {
     entities: Vec<Entry>,
+    // Indices of unfilled entries that may be recycled
+    free: Vec<u32>,
+    // The number of entries in `free` not yet claimed by `reserve`. Becomes negative when fresh entries have been
+    // reserved beyond the end of `entities`.
+    free_cursor: AtomicIsize,
+    // The indices of the entities that have each component, indexed by component id
+    comp_sets: Vec<HiBitSet>,
 }
 
 impl Entities {
     pub fn create(&mut self) -> EntityId {
-        let (idx, gen) = if let Some((idx, entry)) = self.entities
-            .iter_mut()
-            .enumerate()
-            .find(|(_, entry)| entry.gen < u32::MAX && !entry.filled)
-        {
+        self.maintain();
+
+        let (idx, gen) = if let Some(idx) = self.free.pop() {
+            let entry = &mut self.entities[idx as usize];
             entry.gen += 1;
             entry.filled = true;
             entry.comp_mask = BitMask::zero();
             (idx, entry.gen)
         } else {
-            let idx = self.entities.len();
+            let idx = Self::to_idx(self.entities.len());
             self.entities.push(Entry {
                 gen: 0,
                 filled: true,
@@ -53,50 +65,167 @@ impl Entities {
             (idx, 0)
         };
 
-        EntityId {
-            idx: idx
-                .try_into()
-                .unwrap_or_else(|_| panic!("No more entity slots may be allocated!")),
-            gen,
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+
+        EntityId { idx, gen }
+    }
+
+    /// Reserve an entity id from a shared reference. The entity does not exist until [`Entities::maintain`] is called.
+    pub fn reserve(&self) -> EntityId {
+        let n = self.free_cursor.fetch_sub(1, Ordering::Relaxed);
+        if n > 0 {
+            let idx = self.free[n as usize - 1];
+            EntityId { idx, gen: self.entities[idx as usize].gen + 1 }
+        } else {
+            EntityId { idx: Self::to_idx(self.entities.len() + (-n) as usize), gen: 0 }
         }
     }
 
+    /// Materialize all entities reserved since the last call to `maintain`.
+    pub fn maintain(&mut self) {
+        let cursor = *self.free_cursor.get_mut();
+
+        let free_len = if cursor < 0 {
+            let fresh = (-cursor) as usize;
+            self.entities.reserve(fresh);
+            for _ in 0..fresh {
+                self.entities.push(Entry {
+                    gen: 0,
+                    filled: true,
+                    comp_mask: BitMask::zero(),
+                });
+            }
+            0
+        } else {
+            cursor as usize
+        };
+
+        for idx in self.free.drain(free_len..) {
+            let entry = &mut self.entities[idx as usize];
+            entry.gen += 1;
+            entry.filled = true;
+            entry.comp_mask = BitMask::zero();
+        }
+
+        *self.free_cursor.get_mut() = self.free.len() as isize;
+    }
+
     pub fn delete(&mut self, entity: EntityId) {
+        self.maintain();
+
         if let Some(entry) = self.entities.get_mut(entity.idx()) {
             if entry.filled && entry.gen == entity.gen {
                 entry.filled = false;
+                // Slots with an exhausted generation are never recycled
+                if entry.gen < u32::MAX {
+                    self.free.push(entity.idx);
+                    *self.free_cursor.get_mut() = self.free.len() as isize;
+                }
             }
         } else {
             unreachable!("Invariant violated: entity index must always be valid");
         }
     }
 
+    fn to_idx(idx: usize) -> u32 {
+        idx
+            .try_into()
+            .unwrap_or_else(|_| panic!("No more entity slots may be allocated!"))
+    }
+
     pub(crate) fn entry(&self, entity: EntityId) -> Option<&Entry> {
         self.entities
             .get(entity.idx())
-            .filter(|entry| entry.gen == entity.gen)
+            .filter(|entry| entry.filled && entry.gen == entity.gen)
     }
 
     pub(crate) fn entry_mut(&mut self, entity: EntityId) -> Option<&mut Entry> {
         self.entities
             .get_mut(entity.idx())
-            .filter(|entry| entry.gen == entity.gen)
+            .filter(|entry| entry.filled && entry.gen == entity.gen)
     }
 
     pub(crate) fn comp_mask(&self, entity: EntityId) -> Option<&BitMask> {
         self.entry(entity).map(|entry| &entry.comp_mask)
     }
 
-    pub(crate) fn iter_filter<'a>(&'a self, filter: &'a (BitMask, BitMask)) -> EntityIter<'a> {
-        // println!("Entities = {}", self.entities.len());
-        // println!("Mask = {:?}", filter);
-        self.entities
-            .iter()
-            .enumerate()
-            .filter(move |(i, entry)| {
-                // println!("Entry[{}] = {:?}", i, entry);
-                entry.filled && entry.comp_mask.matches(filter)
-            })
+    /// Add the components to the entity's mask, returning the ones that it already had.
+    pub(crate) fn insert_comps(&mut self, entity: EntityId, comps: &BitMask) -> Option<BitMask> {
+        let entry = self.entry_mut(entity)?;
+        let had = entry.comp_mask.clone().intersection(comps.clone());
+        entry.comp_mask |= comps.clone();
+
+        for comp_id in comps.ones() {
+            let comp_id = comp_id as usize;
+            if self.comp_sets.len() <= comp_id {
+                self.comp_sets.resize_with(comp_id + 1, HiBitSet::default);
+            }
+            self.comp_sets[comp_id].insert(entity.idx());
+        }
+
+        Some(had)
+    }
+
+    /// Remove the components from the entity's mask, returning the ones that it had.
+    pub(crate) fn remove_comps(&mut self, entity: EntityId, comps: &BitMask) -> Option<BitMask> {
+        let entry = self.entry_mut(entity)?;
+        let had = entry.comp_mask.clone().intersection(comps.clone());
+        entry.comp_mask = entry.comp_mask.clone().difference(comps.clone());
+
+        for comp_id in had.ones() {
+            self.comp_sets[comp_id as usize].remove(entity.idx());
+        }
+
+        Some(had)
+    }
+
+    pub(crate) fn iter_filter<'a>(&'a self, filter: &'a Filter) -> EntityIter<'a> {
+        self.iter_range(filter, 0..self.entities.len())
+    }
+
+    #[cfg(feature = "rayon")]
+    pub(crate) fn par_iter_filter<'a>(
+        &'a self,
+        filter: &'a Filter,
+    ) -> impl rayon::iter::ParallelIterator<Item = EntityId> + 'a {
+        use rayon::prelude::*;
+
+        // Split the indices into ranges of whole words, which are each visited in order
+        rayon::iter::split(0..self.entities.len(), |range| {
+            if range.len() <= PAR_SPLIT_LEN {
+                return (range, None);
+            }
+            let mid = (range.start + range.len() / 2) & !(WORD_BITS as usize - 1);
+            (range.start..mid, Some(mid..range.end))
+        })
+            .flat_map_iter(move |range| self.iter_range(filter, range))
+    }
+
+    // Visit the entities within a range of indices that match the filter
+    fn iter_range<'a>(&'a self, filter: &'a Filter, range: Range<usize>) -> impl Iterator<Item = EntityId> + 'a {
+        // Only visit entities that have all of the required components, or failing that any of the first set of
+        // alternatives, if there are any
+        let required = filter.required();
+        let entries = if required != BitMask::zero() {
+            let sets = required
+                .ones()
+                .map(|comp_id| self.comp_sets.get(comp_id as usize))
+                .collect::<Option<Vec<_>>>()
+                // A component without a set has never been inserted
+                .unwrap_or_default();
+            Entries::Sets(SetIter::intersection(sets).within(range), &self.entities)
+        } else if let Some(any) = filter.any.first() {
+            let sets = any
+                .ones()
+                .filter_map(|comp_id| self.comp_sets.get(comp_id as usize))
+                .collect();
+            Entries::Sets(SetIter::union(sets).within(range), &self.entities)
+        } else {
+            Entries::All(range.clone().zip(self.entities[range].iter()))
+        };
+
+        entries
+            .filter(move |(_, entry)| entry.filled && entry.comp_mask.matches(filter))
             .map(|(idx, entry)| EntityId {
                 idx: idx as u32,
                 gen: entry.gen,
@@ -104,54 +233,307 @@ impl Entities {
     }
 }
 
+// Ranges of entity indices are split between threads until they are no longer than this
+#[cfg(feature = "rayon")]
+const PAR_SPLIT_LEN: usize = 1024;
+
 pub type EntityIter<'a> = impl Iterator<Item = EntityId> + 'a;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct BitMask(u64);
+enum Entries<'a> {
+    All(std::iter::Zip<Range<usize>, std::slice::Iter<'a, Entry>>),
+    Sets(SetIter<'a>, &'a [Entry]),
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (usize, &'a Entry);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Entries::All(iter) => iter.next(),
+            Entries::Sets(iter, entities) => iter.next().map(|idx| (idx, &entities[idx])),
+        }
+    }
+}
+
+const WORD_BITS: u64 = u64::BITS as u64;
+
+/// A growable set of bits.
+///
+/// The first 64 bits are stored inline, so masks only allocate once more than 64 components have been registered.
+#[derive(Clone, Debug, Default)]
+pub struct BitMask {
+    head: u64,
+    tail: Vec<u64>,
+}
 
 impl BitMask {
-    pub fn zero() -> Self { Self(0) }
+    pub fn zero() -> Self { Self { head: 0, tail: Vec::new() } }
+
+    pub fn with(x: u64) -> Self {
+        let mut this = Self::zero();
+        this.set_bit(x);
+        this
+    }
+
+    fn words(&self) -> usize { 1 + self.tail.len() }
+
+    fn word(&self, i: usize) -> u64 {
+        if i == 0 {
+            self.head
+        } else {
+            self.tail.get(i - 1).copied().unwrap_or(0)
+        }
+    }
+
+    fn word_mut(&mut self, i: usize) -> &mut u64 {
+        if i == 0 {
+            &mut self.head
+        } else {
+            if self.tail.len() < i {
+                self.tail.resize(i, 0);
+            }
+            &mut self.tail[i - 1]
+        }
+    }
+
+    fn zip_with(mut self, other: Self, f: impl Fn(u64, u64) -> u64) -> Self {
+        for i in 0..self.words().max(other.words()) {
+            let word = f(self.word(i), other.word(i));
+            *self.word_mut(i) = word;
+        }
+        self
+    }
+
+    pub fn intersection(self, other: Self) -> Self { self.zip_with(other, |a, b| a & b) }
 
-    pub fn with(x: u64) -> Self { Self(1 << x) }
+    pub fn union(self, other: Self) -> Self { self.zip_with(other, |a, b| a | b) }
 
-    pub fn intersection(self, other: Self) -> Self { Self(self.0 & other.0) }
+    pub fn difference(self, other: Self) -> Self { self.zip_with(other, |a, b| a & !b) }
 
-    pub fn union(self, other: Self) -> Self { Self(self.0 | other.0) }
+    fn intersects(&self, other: &Self) -> bool {
+        (0..self.words().min(other.words())).any(|i| self.word(i) & other.word(i) != 0)
+    }
 
-    pub fn matches(&self, (check, mask): &(Self, Self)) -> bool {
-        self.0 & mask.0 == check.0
+    pub fn matches(&self, Filter { check, mask, any }: &Filter) -> bool {
+        let matches = if mask.tail.is_empty() && check.tail.is_empty() {
+            self.head & mask.head == check.head
+        } else {
+            (0..mask.words().max(check.words()))
+                .all(|i| self.word(i) & mask.word(i) == check.word(i))
+        };
+        matches && any.iter().all(|any| self.intersects(any))
     }
 
     pub fn bit_is_set(&self, x: u64) -> bool {
-        (self.0 >> x) & 1 != 0
+        (self.word((x / WORD_BITS) as usize) >> (x % WORD_BITS)) & 1 != 0
     }
 
     pub fn set_bit(&mut self, x: u64) {
-        self.0 |= 1 << x;
+        *self.word_mut((x / WORD_BITS) as usize) |= 1 << (x % WORD_BITS);
     }
 
     pub fn unset_bit(&mut self, x: u64) {
-        self.0 &= !(1 << x);
+        if (x / WORD_BITS) < self.words() as u64 {
+            *self.word_mut((x / WORD_BITS) as usize) &= !(1 << (x % WORD_BITS));
+        }
     }
 
-    pub fn combine_filters(
-        (a_check, a_mask): (BitMask, BitMask),
-        (b_check, b_mask): (BitMask, BitMask),
-    ) -> Option<(BitMask, BitMask)>
-    {
-        let check = a_check.clone().union(b_check.clone());
-        if check.clone().intersection(a_mask.clone()) == a_check
-            && check.clone().intersection(b_mask.clone()) == b_check
+    /// Iterate over the indices of all set bits, in ascending order.
+    pub fn ones(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.words())
+            .flat_map(move |i| {
+                let mut word = self.word(i);
+                // Pop the lowest set bit until none remain
+                core::iter::from_fn(move || if word == 0 {
+                    None
+                } else {
+                    let bit = word.trailing_zeros() as u64;
+                    word &= word - 1;
+                    Some(i as u64 * WORD_BITS + bit)
+                })
+            })
+    }
+}
+
+/// The components that an entity must have, or not have, to be visited by a query.
+///
+/// An entity matches if its components in `mask` are exactly those in `check`, and it has at least one of the
+/// components in each mask of `any`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub check: BitMask,
+    pub mask: BitMask,
+    pub any: Vec<BitMask>,
+}
+
+impl Filter {
+    /// Matches every entity.
+    pub fn all() -> Self { Self::default() }
+
+    /// Matches entities that have the component.
+    pub fn with(comp_id: u64) -> Self {
+        let mask = BitMask::with(comp_id);
+        Self { check: mask.clone(), mask, any: Vec::new() }
+    }
+
+    /// Matches entities that don't have the component.
+    pub fn without(comp_id: u64) -> Self {
+        Self { check: BitMask::zero(), mask: BitMask::with(comp_id), any: Vec::new() }
+    }
+
+    /// Matches entities that have at least one of the components in the mask.
+    pub fn any_of(mask: BitMask) -> Self {
+        Self { any: vec![mask], ..Self::default() }
+    }
+
+    /// The components that an entity must have to match.
+    pub fn required(&self) -> BitMask { self.check.clone().intersection(self.mask.clone()) }
+
+    /// Combine two filters into one that matches only the entities that both match, or `None` if no entity could.
+    pub fn combine(self, other: Self) -> Option<Self> {
+        let check = self.check.clone().union(other.check.clone());
+        if check.clone().intersection(self.mask.clone()) != self.check
+            || check.clone().intersection(other.mask.clone()) != other.check
         {
-            Some((check, a_mask.union(b_mask)))
-        } else {
-            None
+            return None;
+        }
+
+        let mask = self.mask.union(other.mask);
+        let any = self.any.into_iter().chain(other.any).collect::<Vec<_>>();
+        // Alternatives that are all excluded can never be satisfied
+        let excluded = mask.clone().difference(check.clone());
+        if any.iter().any(|any| any.clone().difference(excluded.clone()) == BitMask::zero()) {
+            return None;
+        }
+
+        Some(Self { check, mask, any })
+    }
+}
+
+// Masks of different lengths are equal if they differ only by trailing zero words
+impl PartialEq for BitMask {
+    fn eq(&self, other: &Self) -> bool {
+        (0..self.words().max(other.words())).all(|i| self.word(i) == other.word(i))
+    }
+}
+
+impl Eq for BitMask {}
+
+impl Hash for BitMask {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Trailing zero words are skipped so that equal masks hash the same
+        let words = (0..self.words()).rev().find(|i| self.word(*i) != 0).map_or(0, |i| i + 1);
+        for i in 0..words {
+            self.word(i).hash(state);
         }
     }
 }
 
 impl BitOrAssign for BitMask {
     fn bitor_assign(&mut self, other: Self) {
-        self.0 |= other.0;
+        for i in 0..other.words() {
+            *self.word_mut(i) |= other.word(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_alive(entities: &Entities, entity: EntityId) -> bool { entities.entry(entity).is_some() }
+
+    #[test]
+    fn reserve_recycled_then_fresh() {
+        let mut entities = Entities::default();
+        let a = entities.create();
+        let b = entities.create();
+        let c = entities.create();
+        entities.delete(a);
+        entities.delete(c);
+
+        // Recycled slots are claimed from the top of the free list, then fresh slots follow the end
+        let r1 = entities.reserve();
+        let r2 = entities.reserve();
+        let r3 = entities.reserve();
+        let r4 = entities.reserve();
+        assert_eq!((r1.idx, r1.gen), (c.idx, c.gen + 1));
+        assert_eq!((r2.idx, r2.gen), (a.idx, a.gen + 1));
+        assert_eq!((r3.idx, r3.gen), (3, 0));
+        assert_eq!((r4.idx, r4.gen), (4, 0));
+
+        // Not usable until maintained
+        assert!(!is_alive(&entities, r1) && !is_alive(&entities, r3));
+
+        entities.maintain();
+        for &r in &[r1, r2, r3, r4] {
+            assert!(is_alive(&entities, r));
+            assert_eq!(entities.comp_mask(r), Some(&BitMask::zero()));
+        }
+        assert!(!is_alive(&entities, a) && !is_alive(&entities, c));
+        assert!(is_alive(&entities, b));
+
+        // Every slot has been claimed
+        assert_eq!(entities.create().idx, 5);
+    }
+
+    #[test]
+    fn reserve_partially_recycled() {
+        let mut entities = Entities::default();
+        let a = entities.create();
+        let b = entities.create();
+        let c = entities.create();
+        entities.delete(a);
+        entities.delete(b);
+        entities.delete(c);
+
+        // Only some of the free slots are claimed, so the rest must stay free
+        let r1 = entities.reserve();
+        assert_eq!((r1.idx, r1.gen), (c.idx, c.gen + 1));
+        entities.maintain();
+        assert!(is_alive(&entities, r1));
+
+        let d = entities.create();
+        assert_eq!((d.idx, d.gen), (b.idx, b.gen + 1));
+        let r2 = entities.reserve();
+        assert_eq!((r2.idx, r2.gen), (a.idx, a.gen + 1));
+        let r3 = entities.reserve();
+        assert_eq!((r3.idx, r3.gen), (3, 0));
+        entities.maintain();
+        assert!([r1, r2, r3, d].iter().all(|e| is_alive(&entities, *e)));
+    }
+
+    #[test]
+    fn create_after_reserve() {
+        let mut entities = Entities::default();
+        let a = entities.create();
+        entities.delete(a);
+
+        let r1 = entities.reserve();
+        let r2 = entities.reserve();
+        // Creating materializes the reservations first, so it must not hand out the same slots
+        let e = entities.create();
+        assert_ne!(e.idx, r1.idx);
+        assert_ne!(e.idx, r2.idx);
+        assert!([r1, r2, e].iter().all(|e| is_alive(&entities, *e)));
+        assert_eq!((r1.idx, r1.gen), (a.idx, a.gen + 1));
+        assert_eq!((r2.idx, r2.gen), (1, 0));
+        assert_eq!((e.idx, e.gen), (2, 0));
+    }
+
+    #[test]
+    fn reserved_gens_match_materialized() {
+        let mut entities = Entities::default();
+        let mut e = entities.create();
+        for gen in 1..5 {
+            entities.delete(e);
+            let r = entities.reserve();
+            assert_eq!((r.idx, r.gen), (e.idx, gen));
+            entities.maintain();
+            assert_eq!(entities.entities[r.idx()].gen, r.gen);
+            assert!(is_alive(&entities, r) && !is_alive(&entities, e));
+            e = r;
+        }
     }
 }
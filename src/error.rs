@@ -0,0 +1,36 @@
+use super::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The named resource is not present in the ECS.
+    MissingResource(&'static str),
+    /// No storage has been inserted for the named component.
+    MissingStorage(&'static str),
+    /// The entity has been deleted, or was never created.
+    DeadEntity(EntityId),
+    /// The named resource could not be borrowed, either because it is already borrowed elsewhere or because a query
+    /// attempts to borrow it in conflicting ways.
+    BorrowConflict(&'static str),
+    /// The inputs of the named system borrow a resource in conflicting ways, so it can never run.
+    SystemConflict { system: &'static str, resource: &'static str },
+    /// The named pattern combines patterns that no entity could match at once, such as `(&C, Not<C>)`.
+    IncompatiblePattern(&'static str),
+    /// The named component appears more than once in a bundle.
+    DuplicateComponent(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MissingResource(name) => write!(f, "Resource `{:?}` is not present in the ECS", name),
+            Error::MissingStorage(name) => write!(f, "Storage for component `{:?}` is not present in the ECS", name),
+            Error::DeadEntity(entity) => write!(f, "Entity {:?} does not exist", entity),
+            Error::BorrowConflict(name) => write!(f, "Conflicting borrows of `{}`", name),
+            Error::SystemConflict { system, resource } => write!(f, "System `{}` has conflicting borrows of `{}`", system, resource),
+            Error::IncompatiblePattern(name) => write!(f, "Pattern `{}` can never match an entity", name),
+            Error::DuplicateComponent(name) => write!(f, "Bundle contains component `{}` more than once", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
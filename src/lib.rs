@@ -8,8 +8,15 @@
     fn_traits,
 )]
 
+pub mod archetype;
+mod bitset;
+pub mod bundle;
+pub mod change;
+pub mod command;
 pub mod component;
+pub mod dispatch;
 pub mod entity;
+pub mod error;
 pub mod query;
 pub mod resource;
 pub mod row;
@@ -17,27 +24,53 @@ pub mod storage;
 pub mod system;
 
 pub use self::{
-    component::Component,
-    entity::{BitMask, EntityId, Entities},
-    query::{Query, Pattern, Not, Maybe, MaybeMut},
-    resource::Resource,
+    archetype::Layout,
+    bundle::Bundle,
+    change::{Added, Changed, RemovedComponents, Ticks},
+    command::{Commands, CommandQueue},
+    component::{Component, Tag},
+    entity::{BitMask, EntityId, Entities, Filter},
+    error::Error,
+    query::{Query, Pattern, ParPattern, Not, With, Maybe, MaybeMut, Or, AnyOf},
+    resource::{Resource, Shareable},
     row::{Read, Write},
-    storage::{Storage, VecStorage},
-    system::{Input, IntoSystem, System},
+    storage::{Storage, ParStorage, ComponentStorage, VecStorage, NullStorage, SparseSetStorage, HashMapStorage, BTreeStorage},
+    system::{Access, Input, IntoSystem, System, SystemState},
+    dispatch::Dispatcher,
 };
 
+#[cfg(feature = "derive")]
+pub use synco_derive::{Component, Bundle, Input};
+
+#[cfg(feature = "rayon")]
+pub use rayon;
+
 use core::any::{Any, type_name};
+use core::fmt;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, atomic::{AtomicU64, Ordering}},
+};
 use self::{
-    component::ComponentId,
+    archetype::{Archetypes, Table},
+    change::{ComponentTicks, RemovalLog},
+    component::{ComponentId, ComponentVTable},
     row::Row,
+    system::StateTick,
 };
 
-use anymap::AnyMap;
+#[cfg(not(feature = "parallel"))]
+type Resources = anymap::AnyMap;
+#[cfg(feature = "parallel")]
+type Resources = anymap::Map<dyn Any + Send + Sync>;
 
 pub struct Ecs {
-    entities: Row<Entities>,
-    component_id: u64,
-    resources: AnyMap,
+    layout: Layout,
+    components: Vec<ComponentVTable>,
+    resources: Resources,
+    tick: AtomicU64,
+    // The last-run ticks of the system states that have been used with this world
+    system_states: Mutex<Vec<StateTick>>,
 }
 
 impl Default for Ecs {
@@ -46,13 +79,24 @@ impl Default for Ecs {
 
 impl Ecs {
     pub fn new() -> Self {
+        Self::new_with_layout(Layout::default())
+    }
+
+    pub fn new_with_layout(layout: Layout) -> Self {
         Self {
-            entities: Row::default(),
-            component_id: 0,
-            resources: AnyMap::new(),
+            layout,
+            components: Vec::new(),
+            resources: Resources::new(),
+            tick: AtomicU64::new(0),
+            system_states: Mutex::new(Vec::new()),
         }
+            .with_resource(Entities::default())
+            .with_resource(CommandQueue::default())
+            .with_resource(Archetypes::default())
     }
 
+    pub fn layout(&self) -> Layout { self.layout }
+
     pub fn insert_resource<R: Resource>(&mut self, res: R) -> Option<R> {
         self.resources.insert(Row::new(res)).map(Row::into_inner)
     }
@@ -62,14 +106,17 @@ impl Ecs {
         self
     }
 
+    fn register_storage<C: Component>(&mut self, vtable: ComponentVTable) {
+        self.insert_resource(ComponentStorage::<C>::default());
+        self.insert_resource(Table::<C>::default());
+        self.insert_resource(ComponentTicks::<C>::default());
+        self.insert_resource(RemovalLog::<C>::default());
+        self.resources.insert(ComponentId::<C>::new(self.components.len() as u64));
+        self.components.push(vtable);
+    }
+
     pub fn insert_storage<C: Component>(&mut self) {
-        if self.component_id >= u64::BITS as u64 {
-            panic!("Too many components!");
-        } else {
-            self.insert_resource(C::Storage::default());
-            self.resources.insert(ComponentId::<C>::new(self.component_id));
-            self.component_id += 1;
-        }
+        self.register_storage::<C>(ComponentVTable::new::<C>());
     }
 
     pub fn with_storage<C: Component>(mut self) -> Self {
@@ -77,6 +124,16 @@ impl Ecs {
         self
     }
 
+    /// Like [`Ecs::insert_storage`], but allows the component to be cloned by [`Ecs::clone_entity`].
+    pub fn insert_cloneable_storage<C: Component + Clone>(&mut self) {
+        self.register_storage::<C>(ComponentVTable::new_cloneable::<C>());
+    }
+
+    pub fn with_cloneable_storage<C: Component + Clone>(mut self) -> Self {
+        self.insert_cloneable_storage::<C>();
+        self
+    }
+
     pub fn with_setup<F: FnOnce(Self) -> Self>(self, setup: F) -> Self {
         setup(self)
     }
@@ -89,116 +146,511 @@ impl Ecs {
         self.resources.get_mut()
     }
 
-    pub(crate) fn resource_inner<R: Resource>(&self) -> &Row<R> {
+    pub(crate) fn try_resource_inner<R: Resource>(&self) -> Result<&Row<R>, Error> {
         self
             .maybe_resource_inner()
-            .unwrap_or_else(|| panic!("Resource `{:?}` is not present in the ECS", type_name::<R>()))
+            .ok_or_else(|| Error::MissingResource(type_name::<R>()))
     }
 
-    pub(crate) fn resource_inner_mut<R: Resource>(&mut self) -> &mut Row<R> {
+    pub(crate) fn try_resource_inner_mut<R: Resource>(&mut self) -> Result<&mut Row<R>, Error> {
         self
             .maybe_resource_inner_mut()
-            .unwrap_or_else(|| panic!("Resource `{:?}` is not present in the ECS", type_name::<R>()))
+            .ok_or_else(|| Error::MissingResource(type_name::<R>()))
     }
 
-    pub(crate) fn storage_id<C: Component>(&self) -> u64 {
+    pub(crate) fn resource_inner_mut<R: Resource>(&mut self) -> &mut Row<R> {
+        self.try_resource_inner_mut().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub(crate) fn try_storage_id<C: Component>(&self) -> Result<u64, Error> {
         self.resources
             .get::<ComponentId<C>>()
             .map(|c_id| c_id.id)
-            .unwrap_or_else(|| panic!("Storage for component `{:?}` is not present in the ECS", type_name::<C>()))
+            .ok_or_else(|| Error::MissingStorage(type_name::<C>()))
     }
 
-    pub fn read_resource<R: Resource>(&self) -> Read<'_, R> { self.resource_inner().read() }
-    pub fn write_resource<R: Resource>(&self) -> Write<'_, R> { self.resource_inner().write() }
+    pub(crate) fn storage_id<C: Component>(&self) -> u64 {
+        self.try_storage_id::<C>().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn read_resource<R: Resource>(&self) -> Read<'_, R> { self.try_read_resource().unwrap_or_else(|err| panic!("{}", err)) }
+    pub fn write_resource<R: Resource>(&self) -> Write<'_, R> { self.try_write_resource().unwrap_or_else(|err| panic!("{}", err)) }
     pub fn mut_resource<R: Resource>(&mut self) -> &mut R { self.resource_inner_mut().get_mut() }
 
+    pub fn try_read_resource<R: Resource>(&self) -> Result<Read<'_, R>, Error> {
+        self.try_resource_inner()?
+            .try_read()
+            .ok_or_else(|| Error::BorrowConflict(type_name::<R>()))
+    }
+
+    pub fn try_write_resource<R: Resource>(&self) -> Result<Write<'_, R>, Error> {
+        self.try_resource_inner()?
+            .try_write()
+            .ok_or_else(|| Error::BorrowConflict(type_name::<R>()))
+    }
+
+    pub fn try_mut_resource<R: Resource>(&mut self) -> Result<&mut R, Error> {
+        Ok(self.try_resource_inner_mut()?.get_mut())
+    }
+
+    pub fn entities(&self) -> Read<'_, Entities> { self.read_resource() }
+    pub(crate) fn entities_mut(&mut self) -> &mut Entities {
+        let entities = self.mut_resource::<Entities>();
+        // Reserved entities should be usable as soon as we have mutable access
+        entities.maintain();
+        entities
+    }
+
     pub fn read_storage<C: Component>(&self) -> ReadStorage<'_, C> {
         ReadStorage {
-            entities: self.entities.read(),
+            entities: self.entities(),
             storage: self.read_resource(),
         }
     }
 
     pub fn write_storage<C: Component>(&self) -> WriteStorage<'_, C> {
         WriteStorage {
-            entities: self.entities.write(),
+            entities: self.write_resource(),
             storage: self.write_resource(),
         }
     }
 
+    /// Advance the world tick, returning the new tick.
+    fn advance_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Query the ECS. Outside of a system, [`Added`] and [`Changed`] match every component.
     pub fn query<P: Pattern>(&self) -> Query<'_, P> {
-        P::fetch(self)
+        P::fetch(self, Ticks { last_run: 0, this_run: self.advance_tick() })
     }
 
+    pub fn try_query<P: Pattern>(&self) -> Result<Query<'_, P>, Error> {
+        P::try_fetch(self, Ticks { last_run: 0, this_run: self.advance_tick() })
+    }
+
+    /// Run a system. [`Added`], [`Changed`] and [`RemovedComponents`] treat every change as new; use
+    /// [`Ecs::run_with_state`] to only observe the changes made since the system last ran.
     pub fn run<'a, S: IntoSystem<'a, T>, T>(&'a self, sys: S) -> <S::System as System<'a>>::Output {
+        self.try_run(sys).unwrap_or_else(|err| panic!("Failed to run system `{}`: {}", type_name::<S>(), err))
+    }
+
+    pub fn try_run<'a, S: IntoSystem<'a, T>, T>(&'a self, sys: S) -> Result<<S::System as System<'a>>::Output, Error> {
+        self.try_run_inner(None, sys)
+    }
+
+    /// Run a system, observing the changes made since it last ran with the same `state`.
+    pub fn run_with_state<'a, S: IntoSystem<'a, T>, T>(&'a self, state: &SystemState, sys: S) -> <S::System as System<'a>>::Output {
+        self.try_run_with_state(state, sys)
+            .unwrap_or_else(|err| panic!("Failed to run system `{}`: {}", type_name::<S>(), err))
+    }
+
+    pub fn try_run_with_state<'a, S: IntoSystem<'a, T>, T>(&'a self, state: &SystemState, sys: S) -> Result<<S::System as System<'a>>::Output, Error> {
+        self.try_run_inner(Some(state), sys)
+    }
+
+    fn try_run_inner<'a, S: IntoSystem<'a, T>, T>(&'a self, state: Option<&SystemState>, sys: S) -> Result<<S::System as System<'a>>::Output, Error> {
+        let access = Access::of_input::<<S::System as System<'a>>::Input>();
+        if let Some(resource) = access.self_conflict() {
+            return Err(Error::SystemConflict { system: type_name::<S>(), resource });
+        }
+
+        let ticks = Ticks {
+            last_run: state.map_or(0, SystemState::last_run),
+            this_run: self.advance_tick(),
+        };
+
         let sys = sys.into_system();
-        let inputs = <S::System as System<'a>>::Input::fetch(self);
-        sys.run(inputs)
+        let inputs = <S::System as System<'a>>::Input::try_fetch(self, ticks)?;
+        if let Some(state) = state {
+            state.set_last_run(self, ticks.this_run);
+        }
+        let output = sys.run(inputs);
+        self.prune_removals(ticks.this_run);
+        Ok(output)
+    }
+
+    pub(crate) fn register_state(&self, state: StateTick) {
+        self.system_states.lock().unwrap().push(state);
+    }
+
+    /// Drop logged removals that every live system state has already seen.
+    fn prune_removals(&self, this_run: u64) {
+        let oldest = {
+            let mut states = self.system_states.lock().unwrap();
+            states.retain(|state| state.strong_count() > 0);
+            states
+                .iter()
+                .filter_map(StateTick::upgrade)
+                .map(|tick| tick.load(Ordering::Relaxed))
+                .min()
+                .unwrap_or(this_run)
+        };
+
+        for vtable in &self.components {
+            // Logs that are still borrowed, such as by systems running in parallel, are pruned next time
+            (vtable.prune)(self, oldest);
+        }
     }
 
     pub fn insert_comp<C: Component>(&mut self, entity: EntityId, comp: C) -> Option<C> {
-        let comp_id = self.storage_id::<C>();
+        self.try_insert_comp(entity, comp).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        let entry = self.entities
-            .get_mut()
-            .entry_mut(entity)
-            .expect("Entity does not exist!");
+    pub fn try_insert_comp<C: Component>(&mut self, entity: EntityId, comp: C) -> Result<Option<C>, Error> {
+        if self.has_comp::<C>(entity)? {
+            C::on_replace(self, entity);
+        }
 
-        let old = if entry.comp_mask.bit_is_set(comp_id) {
-            Some(unsafe { self
-                .mut_resource::<C::Storage>()
-                .remove_unchecked(entity) })
-        } else {
-            entry.comp_mask.set_bit(comp_id);
-            None
-        };
+        // The hook may have removed the component, so check again
+        let old = self.insert_comp_inner(entity, comp)?;
+        if old.is_none() {
+            C::on_insert(self, entity);
+        }
+
+        Ok(old)
+    }
+
+    fn has_comp<C: Component>(&mut self, entity: EntityId) -> Result<bool, Error> {
+        let comp_id = self.try_storage_id::<C>()?;
+        self.entities_mut()
+            .comp_mask(entity)
+            .map(|mask| mask.bit_is_set(comp_id))
+            .ok_or(Error::DeadEntity(entity))
+    }
+
+    fn insert_comp_inner<C: Component>(&mut self, entity: EntityId, comp: C) -> Result<Option<C>, Error> {
+        let comp_id = self.try_storage_id::<C>()?;
 
-        unsafe { self
-            .mut_resource::<C::Storage>()
-            .insert_unchecked(entity, comp) };
+        let had = self.entities_mut()
+            .insert_comps(entity, &BitMask::with(comp_id))
+            .ok_or(Error::DeadEntity(entity))?;
 
-        old
+        self.relocate(entity);
+        // Safety: the component mask has been updated above
+        let old = unsafe { self.put_comp(entity, comp, had.bit_is_set(comp_id)) };
+
+        let tick = self.advance_tick();
+        self.mut_resource::<ComponentTicks<C>>().insert(entity, tick, old.is_some());
+
+        Ok(old)
     }
 
     pub fn remove_comp<C: Component>(&mut self, entity: EntityId) -> Option<C> {
+        self.try_remove_comp(entity).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_remove_comp<C: Component>(&mut self, entity: EntityId) -> Result<Option<C>, Error> {
+        if !self.has_comp::<C>(entity)? {
+            return Ok(None);
+        }
+
+        C::on_remove(self, entity);
+
+        // The hook may have removed the component or deleted the entity, so check again
+        if !self.has_comp::<C>(entity).unwrap_or(false) {
+            return Ok(None);
+        }
+
         let comp_id = self.storage_id::<C>();
+        self.entities_mut().remove_comps(entity, &BitMask::with(comp_id));
+        // Safety: the component mask has been updated above
+        let old = unsafe { self.take_comp::<C>(entity) };
+        self.relocate(entity);
 
-        let entry = self.entities
-            .get_mut()
-            .entry_mut(entity)
-            .expect("Entity does not exist!");
+        let tick = self.advance_tick();
+        self.mut_resource::<RemovalLog<C>>().push(tick, entity);
 
-        let old = if entry.comp_mask.bit_is_set(comp_id) {
-            Some(unsafe { self
-                .mut_resource::<C::Storage>()
-                .remove_unchecked(entity) })
-        } else {
-            entry.comp_mask.unset_bit(comp_id);
-            None
+        Ok(Some(old))
+    }
+
+    /// Store the component of an entity, returning the old component if it was replaced.
+    ///
+    /// Safety: The entity must be alive, its component mask must already include `C` and it must have been moved to its
+    /// new archetype. `replaced` must be whether the entity had a `C` before its component mask was updated.
+    pub(crate) unsafe fn put_comp<C: Component>(&mut self, entity: EntityId, comp: C, replaced: bool) -> Option<C> {
+        match self.layout {
+            Layout::Storage => {
+                let storage = &mut self.mut_resource::<ComponentStorage<C>>().inner;
+                let old = if replaced { Some(storage.remove_unchecked(entity)) } else { None };
+                storage.insert_unchecked(entity, comp);
+                old
+            },
+            Layout::Archetype => {
+                let location = self.mut_resource::<Archetypes>().location(entity);
+                let table = self.mut_resource::<Table<C>>();
+                if replaced {
+                    Some(core::mem::replace(table.get_mut(location), comp))
+                } else {
+                    table.push(location.archetype, comp);
+                    None
+                }
+            },
+        }
+    }
+
+    /// Take the component of an entity.
+    ///
+    /// Safety: The entity must have had a `C` before its component mask was updated to exclude it, and must not have
+    /// been moved to its new archetype yet.
+    pub(crate) unsafe fn take_comp<C: Component>(&mut self, entity: EntityId) -> C {
+        match self.layout {
+            Layout::Storage => self.mut_resource::<ComponentStorage<C>>().inner.remove_unchecked(entity),
+            Layout::Archetype => {
+                let location = self.mut_resource::<Archetypes>().location(entity);
+                self.mut_resource::<Table<C>>().swap_remove(location)
+            },
+        }
+    }
+
+    /// Move the entity, along with the components it has kept, into the archetype that matches its component mask.
+    ///
+    /// Components that were added or removed must be put after or taken before calling this.
+    pub(crate) fn relocate(&mut self, entity: EntityId) {
+        if self.layout != Layout::Archetype {
+            return;
+        }
+
+        let mask = match self.entities_mut().comp_mask(entity) {
+            Some(mask) => mask.clone(),
+            None => return,
+        };
+        let archetypes = self.mut_resource::<Archetypes>();
+        let from = archetypes.location(entity);
+        let to = archetypes.get_or_insert(&mask);
+        if from.archetype == to {
+            return;
+        }
+
+        let kept = archetypes.mask(from.archetype).clone().intersection(mask);
+        archetypes.move_entity(entity, from, to);
+        // The entity's row was removed and pushed in the same way above, so the columns stay in line
+        for comp_id in kept.ones() {
+            (self.components[comp_id as usize].relocate)(self, from, to);
+        }
+    }
+
+    /// Create a new entity with the components in the bundle.
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityId {
+        self.try_spawn(bundle).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_spawn<B: Bundle>(&mut self, bundle: B) -> Result<EntityId, Error> {
+        // Check the bundle before creating the entity, so that none is left behind if it's invalid
+        B::comp_mask(self)?;
+        let entity = self.entities_mut().create();
+        self.try_insert_bundle(entity, bundle)?;
+        Ok(entity)
+    }
+
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: EntityId, bundle: B) {
+        self.try_insert_bundle(entity, bundle).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Insert all components in the bundle into the entity, replacing any that it already has.
+    pub fn try_insert_bundle<B: Bundle>(&mut self, entity: EntityId, bundle: B) -> Result<(), Error> {
+        let bundle_mask = B::comp_mask(self)?;
+
+        let replaced = self.entities_mut()
+            .comp_mask(entity)
+            .ok_or(Error::DeadEntity(entity))?
+            .clone()
+            .intersection(bundle_mask.clone());
+        B::on_replace(self, entity, &replaced);
+
+        // The hooks may have changed the entity's components, so check again
+        let replaced = self.entities_mut()
+            .insert_comps(entity, &bundle_mask)
+            .ok_or(Error::DeadEntity(entity))?;
+
+        self.relocate(entity);
+        let tick = self.advance_tick();
+        // Safety: the component mask has been updated above
+        unsafe { bundle.insert_unchecked(self, entity, &replaced, tick) };
+
+        B::on_insert(self, entity, &bundle_mask.difference(replaced));
+
+        Ok(())
+    }
+
+    pub fn remove_bundle<B: Bundle>(&mut self, entity: EntityId) {
+        self.try_remove_bundle::<B>(entity).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Remove and drop all components in the bundle that the entity has.
+    pub fn try_remove_bundle<B: Bundle>(&mut self, entity: EntityId) -> Result<(), Error> {
+        let bundle_mask = B::comp_mask(self)?;
+
+        let removed = self.entities_mut()
+            .comp_mask(entity)
+            .ok_or(Error::DeadEntity(entity))?
+            .clone()
+            .intersection(bundle_mask.clone());
+        B::on_remove(self, entity, &removed);
+
+        // The hooks may have changed the entity's components or deleted it, so check again
+        let removed = match self.entities_mut().remove_comps(entity, &bundle_mask) {
+            Some(removed) => removed,
+            None => return Ok(()),
+        };
+
+        let tick = self.advance_tick();
+        // Safety: the component mask has been updated above
+        unsafe { B::remove_unchecked(self, entity, &removed, tick) };
+        self.relocate(entity);
+
+        Ok(())
+    }
+
+    /// Remove and drop every component of type `C`.
+    pub fn clear_comps<C: Component>(&mut self) {
+        self.retain_comps::<C>(|_, _| false);
+    }
+
+    /// Remove and drop every component of type `C` for which `f` returns `false`.
+    pub fn retain_comps<C: Component>(&mut self, mut f: impl FnMut(EntityId, &mut C) -> bool) {
+        let removed = match self.layout {
+            Layout::Storage => {
+                // Not every storage knows which entities its components belong to, so take them from the entities
+                let filter = Filter::with(self.storage_id::<C>());
+                let entities = self.read_resource::<Entities>();
+                let mut storage = self.write_resource::<ComponentStorage<C>>();
+                entities
+                    .iter_filter(&filter)
+                    // Safety: the filter only matches entities that have the component
+                    .filter(|entity| !f(*entity, &mut *unsafe { storage.inner.get_unchecked_mut(*entity) }))
+                    .collect::<Vec<_>>()
+            },
+            Layout::Archetype => {
+                let filter = Filter::with(self.storage_id::<C>());
+                let archetypes = self.read_resource::<Archetypes>();
+                let mut table = self.write_resource::<Table<C>>();
+                let mut removed = Vec::new();
+                for archetype in archetypes.matching(&filter) {
+                    for (entity, comp) in archetypes.entities(archetype).iter().zip(table.iter_mut(archetype)) {
+                        if !f(*entity, comp) {
+                            removed.push(*entity);
+                        }
+                    }
+                }
+                removed
+            },
         };
 
-        old
+        for entity in removed {
+            // Removal hooks may have deleted the entity
+            let _ = self.try_remove_comp::<C>(entity);
+        }
+    }
+
+    /// Delete an entity, dropping all of its components. Does nothing if the entity is already dead.
+    pub fn delete(&mut self, entity: EntityId) {
+        // Removal hooks may insert other components, so keep going until none remain
+        while let Some(comp_id) = self.entities_mut()
+            .comp_mask(entity)
+            .and_then(|mask| mask.ones().next())
+        {
+            (self.components[comp_id as usize].drop)(self, entity);
+        }
+
+        self.entities_mut().delete(entity);
+    }
+
+    /// Create a new entity with clones of all components of an existing entity.
+    ///
+    /// Panics if the entity has a component that was not registered with [`Ecs::insert_cloneable_storage`].
+    pub fn clone_entity(&mut self, entity: EntityId) -> EntityId {
+        let comp_mask = self.entities_mut()
+            .comp_mask(entity)
+            .expect("Attempted to clone non-existent entity")
+            .clone();
+
+        let new_entity = self.entities_mut().create();
+
+        for comp_id in comp_mask.ones() {
+            let vtable = &self.components[comp_id as usize];
+            let clone = vtable.clone
+                .unwrap_or_else(|| panic!("Component `{:?}` is not cloneable", vtable.name));
+            clone(self, entity, new_entity);
+        }
+
+        new_entity
     }
 
     pub fn modify(&mut self, entity: EntityId) -> Entity<'_> {
-        assert!(self.entities.get_mut().entry(entity).is_some(), "Attempted to modify non-existent entity");
-        Entity { entity, ecs: self }
+        self.try_modify(entity).expect("Attempted to modify non-existent entity")
+    }
+
+    pub fn try_modify(&mut self, entity: EntityId) -> Result<Entity<'_>, Error> {
+        if self.entities_mut().entry(entity).is_some() {
+            Ok(Entity { entity, ecs: self })
+        } else {
+            Err(Error::DeadEntity(entity))
+        }
     }
 
     pub fn create(&mut self) -> Entity<'_> {
-        let entity = self.entities.get_mut().create();
+        let entity = self.entities_mut().create();
         Entity { entity, ecs: self }
     }
+
+    /// Reserve an entity id without requiring mutable access to the ECS.
+    ///
+    /// The entity becomes usable after [`Ecs::maintain`], or the next time the ECS is modified.
+    pub fn reserve(&self) -> EntityId {
+        self.entities().reserve()
+    }
+
+    /// Materialize all entities reserved since the last call to `maintain`, and prune old component removals.
+    pub fn maintain(&mut self) {
+        self.entities_mut().maintain();
+
+        let tick = self.advance_tick();
+        for comp_id in 0..self.components.len() {
+            (self.components[comp_id].maintain)(self, tick);
+        }
+    }
+
+    /// Apply all structural changes recorded by [`Commands`], in the order they were recorded.
+    pub fn apply_commands(&mut self) {
+        self.entities_mut();
+        for command in self.mut_resource::<CommandQueue>().take() {
+            command.apply(self);
+        }
+    }
+}
+
+impl fmt::Debug for Ecs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The number of components in a storage, unless it is borrowed mutably
+        struct Len(Result<usize, Error>);
+
+        impl fmt::Debug for Len {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match &self.0 {
+                    Ok(len) => write!(f, "{}", len),
+                    Err(_) => write!(f, "<borrowed>"),
+                }
+            }
+        }
+
+        f.debug_struct("Ecs")
+            .field("components", &self.components
+                .iter()
+                .map(|vtable| (vtable.name, Len((vtable.len)(self))))
+                .collect::<Vec<_>>())
+            .finish()
+    }
 }
 
 pub struct ReadStorage<'a, C: Component> {
     entities: Read<'a, Entities>,
-    storage: Read<'a, C::Storage>,
+    storage: Read<'a, ComponentStorage<C>>,
 }
 
 pub struct WriteStorage<'a, C: Component> {
     entities: Write<'a, Entities>,
-    storage: Write<'a, C::Storage>,
+    storage: Write<'a, ComponentStorage<C>>,
 }
 
 pub struct Entity<'a> {
@@ -220,5 +672,19 @@ impl<'a> Entity<'a> {
         self.ecs.remove_comp::<C>(self.entity)
     }
 
+    pub fn insert_bundle<B: Bundle>(&mut self, bundle: B) {
+        self.ecs.insert_bundle(self.entity, bundle);
+    }
+
+    pub fn remove_bundle<B: Bundle>(&mut self) {
+        self.ecs.remove_bundle::<B>(self.entity);
+    }
+
+    pub fn delete(self) {
+        self.ecs.delete(self.entity);
+    }
+
     pub fn id(&self) -> EntityId { self.entity }
+
+    pub fn finish(self) -> EntityId { self.entity }
 }
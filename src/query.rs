@@ -1,13 +1,17 @@
-use super::{*, entity::EntityIter};
+use super::{*, archetype::{Archetypes, Table}, entity::EntityIter, change::ComponentTicks};
 
 use std::{
     cell::{UnsafeCell, Ref},
     marker::PhantomData,
 };
+#[cfg(feature = "rayon")]
+use rayon::iter::{Either, IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 pub struct Query<'a, P: Pattern> {
     entities: Read<'a, Entities>,
-    filter: (BitMask, BitMask),
+    // Only fetched for worlds with `Layout::Archetype`
+    archetypes: Option<Read<'a, Archetypes>>,
+    filter: Filter,
     state: UnsafeCell<P::State<'a>>,
 }
 
@@ -17,6 +21,7 @@ impl<'a, P: Pattern> Clone for Query<'a, P>
     fn clone(&self) -> Self {
         Self {
             entities: self.entities.clone(),
+            archetypes: self.archetypes.clone(),
             filter: self.filter.clone(),
             state: UnsafeCell::new(unsafe { &*self.state.get() }.clone()),
         }
@@ -25,105 +30,364 @@ impl<'a, P: Pattern> Clone for Query<'a, P>
 
 impl<'a, P: Pattern> Query<'a, P> {
     pub fn iter(&mut self) -> QueryIter<'_, 'a, P> {
+        let dense = if self.archetypes.is_none() { self.dense() } else { None };
+        let rows = match (&self.archetypes, dense) {
+            // Entities without components aren't kept in an archetype, so they must be found some other way
+            (Some(archetypes), _) if BitMask::zero().matches(&self.filter) =>
+                Rows::Located(self.entities.iter_filter(&self.filter), archetypes),
+            (Some(archetypes), _) => Rows::Archetypes {
+                archetypes,
+                matching: archetypes.matching(&self.filter).collect::<Vec<_>>().into_iter(),
+                current: None,
+            },
+            (None, Some(dense)) => Rows::Dense(dense.into_iter()),
+            (None, None) => Rows::All(self.entities.iter_filter(&self.filter)),
+        };
+
         QueryIter {
             state: &mut self.state,
-            entities: self.entities.iter_filter(&self.filter),
+            rows,
+        }
+    }
+
+    /// Iterate over the pattern's outputs in parallel. Components that the pattern borrows mutably must be kept in a
+    /// [`ParStorage`].
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&mut self) -> impl ParallelIterator<Item = P::Output<'_>> + Captures<'a> + '_
+        where P: ParPattern, for<'b> P::Output<'b>: Send, P::State<'a>: Sync
+    {
+        let dense = if self.archetypes.is_none() { self.dense() } else { None };
+        let (entities, archetypes, filter) = (&self.entities, self.archetypes.as_deref(), &self.filter);
+        // Outputs are fetched from several threads through a shared reference to the state
+        let state = &*self.state.get_mut();
+
+        // Safety: each entity is visited only once, so outputs never alias
+        let fetch = move |entity: EntityId| unsafe {
+            if !P::matches(state, entity) {
+                return None;
+            }
+            Some(match archetypes {
+                Some(archetypes) => {
+                    let location = archetypes.location(entity);
+                    let columns = P::columns(state, location.archetype, archetypes.mask(location.archetype));
+                    P::get_column(state, &columns, entity, location.row)
+                },
+                None => P::get_shared(state, entity),
+            })
+        };
+
+        match (archetypes, dense) {
+            (Some(archetypes), _) if !BitMask::zero().matches(filter) => Either::Left(archetypes
+                .matching(filter)
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .flat_map(move |archetype| archetypes
+                    .entities(archetype)
+                    .par_iter()
+                    .enumerate()
+                    // Columns are looked up once for each chunk of rows that a thread takes on
+                    .map_init(
+                        move || P::columns(state, archetype, archetypes.mask(archetype)),
+                        move |columns, (row, entity)| unsafe {
+                            if P::matches(state, *entity) {
+                                Some(P::get_column(state, columns, *entity, row))
+                            } else {
+                                None
+                            }
+                        },
+                    )
+                    .flatten())),
+            (_, Some(dense)) => Either::Right(Either::Left(dense.into_par_iter().filter_map(fetch))),
+            _ => Either::Right(Either::Right(entities.par_iter_filter(filter).filter_map(fetch))),
         }
     }
 
+    /// Call `f` with each of the pattern's outputs in parallel.
+    #[cfg(feature = "rayon")]
+    pub fn par_for_each(&mut self, f: impl for<'b> Fn(P::Output<'b>) + Send + Sync)
+        where P: ParPattern, for<'b> P::Output<'b>: Send, P::State<'a>: Sync
+    {
+        self.par_iter().for_each(f);
+    }
+
+    // The entities in the smallest packed storage that match the filter, if any of the storages are packed
+    fn dense(&mut self) -> Option<Vec<EntityId>> {
+        let (entities, filter) = (&self.entities, &self.filter);
+        P::dense(self.state.get_mut()).map(|dense| dense
+            .iter()
+            .copied()
+            .filter(|entity| entities
+                .comp_mask(*entity)
+                .map_or(false, |mask| mask.matches(filter)))
+            .collect())
+    }
+
     pub fn get(&mut self, entity: EntityId) -> Option<P::Output<'_>> {
-        if self.entities.comp_mask(entity)?.matches(&self.filter) {
-            // Safety: filter has been checked, access must be valid
-            Some(unsafe { P::get_unchecked(self.state.get_mut(), entity) })
+        // Safety: filter is checked before the pattern is matched, access must be valid
+        if self.entities.comp_mask(entity)?.matches(&self.filter)
+            && unsafe { P::matches(self.state.get_mut(), entity) }
+        {
+            Some(match &self.archetypes {
+                Some(archetypes) => {
+                    let location = archetypes.location(entity);
+                    let state = self.state.get_mut();
+                    let columns = P::columns(state, location.archetype, archetypes.mask(location.archetype));
+                    unsafe { P::get_column(state, &columns, entity, location.row) }
+                },
+                None => unsafe { P::get_unchecked(self.state.get_mut(), entity) },
+            })
         } else {
             None
         }
     }
 }
 
+/// Allows `impl Trait` return types to capture lifetimes that they are not bounded by.
+#[cfg(feature = "rayon")]
+#[doc(hidden)]
+pub trait Captures<'a> {}
+
+#[cfg(feature = "rayon")]
+impl<'a, T: ?Sized> Captures<'a> for T {}
+
+enum Rows<'a, P: Pattern> {
+    All(EntityIter<'a>),
+    Dense(std::vec::IntoIter<EntityId>),
+    // The rows of each matching archetype in turn, along with the pattern's columns in the current archetype
+    Archetypes {
+        archetypes: &'a Archetypes,
+        matching: std::vec::IntoIter<usize>,
+        current: Option<(P::Columns<'a>, std::iter::Enumerate<std::slice::Iter<'a, EntityId>>)>,
+    },
+    Located(EntityIter<'a>, &'a Archetypes),
+}
+
 pub struct QueryIter<'a, 'b, P: Pattern> {
     state: &'a mut UnsafeCell<P::State<'b>>,
-    entities: EntityIter<'a>,
+    rows: Rows<'a, P>,
 }
 
 impl<'a, 'b: 'a, P: Pattern> Iterator for QueryIter<'a, 'b, P> {
     type Item = P::Output<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let entity = self.entities.next()?;
-        Some(unsafe { P::get_unchecked(&mut *self.state.get(), entity) })
+        let state = self.state.get();
+        // Safety: each entity is visited only once, so outputs never alias. In worlds with `Layout::Archetype`, the
+        // state is only ever borrowed immutably.
+        unsafe {
+            match &mut self.rows {
+                Rows::All(iter) => iter
+                    .find(|entity| P::matches(&*state, *entity))
+                    .map(|entity| P::get_unchecked(&mut *state, entity)),
+                Rows::Dense(iter) => iter
+                    .find(|entity| P::matches(&*state, *entity))
+                    .map(|entity| P::get_unchecked(&mut *state, entity)),
+                Rows::Archetypes { archetypes, matching, current } => loop {
+                    if let Some((columns, rows)) = current {
+                        match rows.next() {
+                            Some((row, entity)) if P::matches(&*state, *entity) =>
+                                break Some(P::get_column(&*state, columns, *entity, row)),
+                            Some(_) => continue,
+                            None => {},
+                        }
+                    }
+                    let archetype = matching.next()?;
+                    *current = Some((
+                        P::columns(&*state, archetype, archetypes.mask(archetype)),
+                        archetypes.entities(archetype).iter().enumerate(),
+                    ));
+                },
+                Rows::Located(iter, archetypes) => {
+                    let entity = iter.find(|entity| P::matches(&*state, *entity))?;
+                    let location = archetypes.location(entity);
+                    let columns = P::columns(&*state, location.archetype, archetypes.mask(location.archetype));
+                    Some(P::get_column(&*state, &columns, entity, location.row))
+                },
+            }
+        }
     }
 }
 
 pub trait Pattern: Sized {
     type State<'a>: 'a;
     type Output<'a>: 'a;
+    /// The pattern's columns in a single archetype of a [`Layout::Archetype`] world.
+    type Columns<'a>: 'a;
+
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error>;
+
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error>;
 
-    fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask);
+    /// Declare the resources that [`Pattern::fetch_inner`] will borrow.
+    fn access(access: &mut Access);
 
-    fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a>;
+    fn fetch<'a>(ecs: &'a Ecs, ticks: Ticks) -> Query<'a, Self> {
+        Self::try_fetch(ecs, ticks)
+            .unwrap_or_else(|err| panic!("Failed to fetch query `{}`: {}", type_name::<Self>(), err))
+    }
 
-    fn fetch<'a>(ecs: &'a Ecs) -> Query<'a, Self> {
-        Query {
-            entities: ecs.entities.read(),
-            filter: Self::comp_filter(ecs),
-            state: UnsafeCell::new(Self::fetch_inner(ecs)),
+    fn try_fetch<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Query<'a, Self>, Error> {
+        if let Some(conflict) = Access::of_pattern::<Self>().self_conflict() {
+            return Err(Error::BorrowConflict(conflict));
         }
+
+        Ok(Query {
+            entities: ecs.try_read_resource()?,
+            archetypes: match ecs.layout() {
+                Layout::Storage => None,
+                Layout::Archetype => Some(ecs.try_read_resource()?),
+            },
+            filter: Self::comp_filter(ecs)?,
+            state: UnsafeCell::new(Self::fetch_inner(ecs, ticks)?),
+        })
     }
 
+    /// Filter entities beyond what [`Pattern::comp_filter`] can express.
+    ///
+    /// Safety: The entity must match the pattern's component filter.
+    unsafe fn matches<'b>(state: &Self::State<'b>, entity: EntityId) -> bool { true }
+
+    /// The entities that the pattern is limited to, if it can narrow them down without visiting every entity.
+    fn dense<'a, 'b: 'a>(state: &'a Self::State<'b>) -> Option<&'a [EntityId]> { None }
+
     unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a>;
+
+    /// Look up the pattern's columns in an archetype with the given components, once for all of its rows.
+    fn columns<'a, 'b: 'a>(state: &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a>;
+
+    /// Like [`Pattern::get_unchecked`], for worlds with [`Layout::Archetype`].
+    ///
+    /// Safety: As with [`Pattern::get_unchecked`], the columns must be those of the entity's archetype and `row` its
+    /// row within them. The output for an entity must not be fetched again while an earlier one is still in use.
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, columns: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a>;
+}
+
+/// A pattern that [`Query::par_iter`] can fetch from several threads at once. Patterns that borrow components mutably
+/// only implement this if their storage is a [`ParStorage`].
+pub trait ParPattern: Pattern {
+    /// Like [`Pattern::get_unchecked`], but through a shared reference to the state.
+    ///
+    /// Safety: As with [`Pattern::get_unchecked`], and the output for an entity must not be fetched again while an
+    /// earlier one is still in use.
+    unsafe fn get_shared<'a, 'b: 'a>(state: &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a>;
 }
 
 impl<'c, C: Component> Pattern for &'c C
     where for<'a> C::Storage: Storage<C, Ref<'a> = &'a C>
 {
-    type State<'a> = Read<'a, C::Storage>;
+    type State<'a> = (Read<'a, ComponentStorage<C>>, Read<'a, Table<C>>);
     type Output<'a> = &'a C;
+    type Columns<'a> = &'a [C];
 
-    fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask) {
-        let mask = BitMask::with(ecs.storage_id::<C>() as u64);
-        (mask.clone(), mask)
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+        Ok(Filter::with(ecs.try_storage_id::<C>()?))
     }
 
-    fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a> { ecs.read_resource() }
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+        Ok((ecs.try_read_resource()?, ecs.try_read_resource()?))
+    }
 
-    unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
-        state.get_unchecked(entity)
+    fn access(access: &mut Access) {
+        access.read::<ComponentStorage<C>>();
+        access.read::<Table<C>>();
+    }
+
+    fn dense<'a, 'b: 'a>((storage, _): &'a Self::State<'b>) -> Option<&'a [EntityId]> { storage.dense() }
+
+    unsafe fn get_unchecked<'a, 'b: 'a>((storage, _): &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+        storage.get_unchecked(entity)
+    }
+
+    fn columns<'a, 'b: 'a>((_, table): &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {
+        table.column(archetype)
+    }
+
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, column: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {
+        column.get_unchecked(row)
+    }
+}
+
+impl<'c, C: Component> ParPattern for &'c C
+    where for<'a> C::Storage: Storage<C, Ref<'a> = &'a C>
+{
+    unsafe fn get_shared<'a, 'b: 'a>((storage, _): &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+        storage.get_unchecked(entity)
     }
 }
 
 impl<'c, C: Component> Pattern for &'c mut C
     where for<'a> C::Storage: Storage<C, RefMut<'a> = &'a mut C>
 {
-    type State<'a> = Write<'a, C::Storage>;
+    type State<'a> = (Write<'a, ComponentStorage<C>>, Write<'a, Table<C>>, Read<'a, ComponentTicks<C>>, u64);
     type Output<'a> = &'a mut C;
+    type Columns<'a> = &'a [UnsafeCell<C>];
 
-    fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask) {
-        let mask = BitMask::with(ecs.storage_id::<C>() as u64);
-        (mask.clone(), mask)
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+        Ok(Filter::with(ecs.try_storage_id::<C>()?))
     }
 
-    fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a> { ecs.write_resource() }
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+        Ok((ecs.try_write_resource()?, ecs.try_write_resource()?, ecs.try_read_resource()?, ticks.this_run))
+    }
 
-    unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
-        state.get_unchecked_mut(entity)
+    fn access(access: &mut Access) {
+        access.write::<ComponentStorage<C>>();
+        access.write::<Table<C>>();
+        access.read::<ComponentTicks<C>>();
+    }
+
+    fn dense<'a, 'b: 'a>((storage, _, _, _): &'a Self::State<'b>) -> Option<&'a [EntityId]> { storage.dense() }
+
+    unsafe fn get_unchecked<'a, 'b: 'a>((storage, _, ticks, this_run): &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+        ticks.mark_changed(entity, *this_run);
+        storage.inner.get_unchecked_mut(entity)
+    }
+
+    fn columns<'a, 'b: 'a>((_, table, _, _): &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {
+        table.cells(archetype)
+    }
+
+    unsafe fn get_column<'a, 'b: 'a>((_, _, ticks, this_run): &'a Self::State<'b>, column: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {
+        ticks.mark_changed(entity, *this_run);
+        &mut *column.get_unchecked(row).get()
+    }
+}
+
+impl<'c, C: Component> ParPattern for &'c mut C
+    where for<'a> C::Storage: ParStorage<C, RefMut<'a> = &'a mut C>
+{
+    unsafe fn get_shared<'a, 'b: 'a>((storage, _, ticks, this_run): &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+        ticks.mark_changed(entity, *this_run);
+        storage.get_unchecked_shared(entity)
     }
 }
 
 impl Pattern for EntityId {
     type State<'a> = ();
     type Output<'a> = EntityId;
+    type Columns<'a> = ();
 
-    fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask) {
-        let mask = BitMask::zero();
-        (mask.clone(), mask)
-    }
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> { Ok(Filter::all()) }
+
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> { Ok(()) }
 
-    fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a> { () }
+    fn access(access: &mut Access) {}
 
     unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
         entity
     }
+
+    fn columns<'a, 'b: 'a>(state: &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {}
+
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, columns: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {
+        entity
+    }
+}
+
+impl ParPattern for EntityId {
+    unsafe fn get_shared<'a, 'b: 'a>(state: &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+        entity
+    }
 }
 
 pub struct Not<C: Component>(PhantomData<C>);
@@ -131,32 +395,96 @@ pub struct Not<C: Component>(PhantomData<C>);
 impl<C: Component> Pattern for Not<C> {
     type State<'a> = ();
     type Output<'a> = ();
+    type Columns<'a> = ();
 
-    fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask) {
-        (BitMask::zero(), BitMask::with(ecs.storage_id::<C>() as u64))
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+        Ok(Filter::without(ecs.try_storage_id::<C>()?))
     }
 
-    fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a> {}
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> { Ok(()) }
+
+    fn access(access: &mut Access) {}
 
     unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
+
+    fn columns<'a, 'b: 'a>(state: &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {}
+
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, columns: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {}
+}
+
+impl<C: Component> ParPattern for Not<C> {
+    unsafe fn get_shared<'a, 'b: 'a>(state: &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
+}
+
+/// Matches entities that have the component, without borrowing its storage.
+pub struct With<C: Component>(PhantomData<C>);
+
+impl<C: Component> Pattern for With<C> {
+    type State<'a> = ();
+    type Output<'a> = ();
+    type Columns<'a> = ();
+
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+        Ok(Filter::with(ecs.try_storage_id::<C>()?))
+    }
+
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> { Ok(()) }
+
+    fn access(access: &mut Access) {}
+
+    unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
+
+    fn columns<'a, 'b: 'a>(state: &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {}
+
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, columns: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {}
+}
+
+impl<C: Component> ParPattern for With<C> {
+    unsafe fn get_shared<'a, 'b: 'a>(state: &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {}
 }
 
 pub struct Maybe<C: Component>(PhantomData<C>);
 
-impl<C: Component> Pattern for Maybe<C> {
-    type State<'a> = (Read<'a, Entities>, Read<'a, C::Storage>, u64);
-    type Output<'a> = Option<<C::Storage as Storage<C>>::Ref<'a>>;
+impl<C: Component> Pattern for Maybe<C>
+    where for<'a> C::Storage: Storage<C, Ref<'a> = &'a C>
+{
+    type State<'a> = (Read<'a, Entities>, Read<'a, ComponentStorage<C>>, Read<'a, Table<C>>, u64);
+    type Output<'a> = Option<&'a C>;
+    type Columns<'a> = Option<&'a [C]>;
+
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> { Ok(Filter::all()) }
+
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+        Ok((ecs.try_read_resource()?, ecs.try_read_resource()?, ecs.try_read_resource()?, ecs.try_storage_id::<C>()?))
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<Entities>();
+        access.read::<ComponentStorage<C>>();
+        access.read::<Table<C>>();
+    }
+
+    unsafe fn get_unchecked<'a, 'b: 'a>(state: &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+        Self::get_shared(state, entity)
+    }
 
-    fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask) {
-        let mask = BitMask::zero();
-        (mask.clone(), mask)
+    fn columns<'a, 'b: 'a>((_, _, table, comp_id): &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {
+        if mask.bit_is_set(*comp_id) {
+            Some(table.column(archetype))
+        } else {
+            None
+        }
     }
 
-    fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a> {
-        (ecs.entities.read(), ecs.read_resource(), ecs.storage_id::<C>() as u64)
+    unsafe fn get_column<'a, 'b: 'a>(state: &'a Self::State<'b>, column: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {
+        column.map(|column| column.get_unchecked(row))
     }
+}
 
-    unsafe fn get_unchecked<'a, 'b: 'a>((entities, storage, comp_id): &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+impl<C: Component> ParPattern for Maybe<C>
+    where for<'a> C::Storage: Storage<C, Ref<'a> = &'a C>
+{
+    unsafe fn get_shared<'a, 'b: 'a>((entities, storage, _, comp_id): &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
         if entities.entry(entity)?.comp_mask.bit_is_set(*comp_id) {
             Some(storage.get_unchecked(entity))
         } else {
@@ -167,79 +495,279 @@ impl<C: Component> Pattern for Maybe<C> {
 
 pub struct MaybeMut<C: Component>(PhantomData<C>);
 
-impl<C: Component> Pattern for MaybeMut<C> {
-    type State<'a> = (Read<'a, Entities>, Write<'a, C::Storage>, u64);
-    type Output<'a> = Option<<C::Storage as Storage<C>>::RefMut<'a>>;
+impl<C: Component> Pattern for MaybeMut<C>
+    where for<'a> C::Storage: Storage<C, RefMut<'a> = &'a mut C>
+{
+    type State<'a> = (Read<'a, Entities>, Write<'a, ComponentStorage<C>>, Write<'a, Table<C>>, u64, Read<'a, ComponentTicks<C>>, u64);
+    type Output<'a> = Option<&'a mut C>;
+    type Columns<'a> = Option<&'a [UnsafeCell<C>]>;
+
+    fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> { Ok(Filter::all()) }
+
+    fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+        Ok((
+            ecs.try_read_resource()?,
+            ecs.try_write_resource()?,
+            ecs.try_write_resource()?,
+            ecs.try_storage_id::<C>()?,
+            ecs.try_read_resource()?,
+            ticks.this_run,
+        ))
+    }
 
-    fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask) {
-        let mask = BitMask::zero();
-        (mask.clone(), mask)
+    fn access(access: &mut Access) {
+        access.read::<Entities>();
+        access.write::<ComponentStorage<C>>();
+        access.write::<Table<C>>();
+        access.read::<ComponentTicks<C>>();
     }
 
-    fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a> {
-        (ecs.entities.read(), ecs.write_resource(), ecs.storage_id::<C>() as u64)
+    unsafe fn get_unchecked<'a, 'b: 'a>((entities, storage, _, comp_id, ticks, this_run): &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+        if entities.entry(entity)?.comp_mask.bit_is_set(*comp_id) {
+            ticks.mark_changed(entity, *this_run);
+            Some(storage.inner.get_unchecked_mut(entity))
+        } else {
+            None
+        }
+    }
+
+    fn columns<'a, 'b: 'a>((_, _, table, comp_id, _, _): &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {
+        if mask.bit_is_set(*comp_id) {
+            Some(table.cells(archetype))
+        } else {
+            None
+        }
     }
 
-    unsafe fn get_unchecked<'a, 'b: 'a>((entities, storage, comp_id): &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+    unsafe fn get_column<'a, 'b: 'a>((_, _, _, _, ticks, this_run): &'a Self::State<'b>, column: &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {
+        let column = (*column)?;
+        ticks.mark_changed(entity, *this_run);
+        Some(&mut *column.get_unchecked(row).get())
+    }
+
+}
+
+impl<C: Component> ParPattern for MaybeMut<C>
+    where for<'a> C::Storage: ParStorage<C, RefMut<'a> = &'a mut C>
+{
+    unsafe fn get_shared<'a, 'b: 'a>((entities, storage, _, comp_id, ticks, this_run): &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
         if entities.entry(entity)?.comp_mask.bit_is_set(*comp_id) {
-            Some(storage.get_unchecked_mut(entity))
+            ticks.mark_changed(entity, *this_run);
+            Some(storage.get_unchecked_shared(entity))
         } else {
             None
         }
     }
 }
 
+/// Matches entities that match any of the patterns in the tuple `T`, yielding an `Option` for each of them.
+pub struct Or<T>(PhantomData<T>);
+
+/// Another name for [`Or`], which reads better with many patterns.
+pub type AnyOf<T> = Or<T>;
+
 macro_rules! impl_for_tuple {
-    ($($x:ident),*) => {
+    ($($x:ident $c:ident),*) => {
         impl<$($x: Pattern),*> Pattern for ($($x,)*) {
             type State<'a> = ($($x::State<'a>,)*);
             type Output<'a> = ($($x::Output<'a>,)*);
+            type Columns<'a> = ($($x::Columns<'a>,)*);
+
+            fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+                let filter = Filter::all();
+                $(let filter = filter
+                    .combine($x::comp_filter(ecs)?)
+                    .ok_or(Error::IncompatiblePattern(type_name::<Self>()))?;)*
+                Ok(filter)
+            }
+
+            fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+                Ok(($($x::fetch_inner(ecs, ticks)?,)*))
+            }
 
-            fn comp_filter(ecs: &Ecs) -> (BitMask, BitMask) {
-                let filter = (BitMask::zero(), BitMask::zero());
-                $(let filter = {
-                    let new_filter = $x::comp_filter(ecs);
-                    BitMask::combine_filters(filter, new_filter)
-                        .unwrap_or_else(|| panic!("Incompatible pattern: {}", type_name::<$x>()))
-                };);*
-                filter
+            fn access(access: &mut Access) {
+                $($x::access(access);)*
             }
 
-            fn fetch_inner<'a>(ecs: &'a Ecs) -> Self::State<'a> {
-                ($($x::fetch_inner(ecs),)*)
+            #[allow(non_snake_case)]
+            unsafe fn matches<'b>(($($x,)*): &Self::State<'b>, entity: EntityId) -> bool {
+                true $(&& $x::matches($x, entity))*
+            }
+
+            #[allow(non_snake_case)]
+            fn dense<'a, 'b: 'a>(($($x,)*): &'a Self::State<'b>) -> Option<&'a [EntityId]> {
+                // Pick the smallest
+                let dense: Option<&[EntityId]> = None;
+                $(let dense = match (dense, $x::dense($x)) {
+                    (Some(a), Some(b)) if b.len() < a.len() => Some(b),
+                    (None, b) => b,
+                    (a, _) => a,
+                };)*
+                dense
             }
 
             #[allow(non_snake_case)]
             unsafe fn get_unchecked<'a, 'b: 'a>(($($x,)*): &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
                 ($($x::get_unchecked($x, entity),)*)
             }
+
+            #[allow(non_snake_case)]
+            fn columns<'a, 'b: 'a>(($($x,)*): &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {
+                ($($x::columns($x, archetype, mask),)*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn get_column<'a, 'b: 'a>(($($x,)*): &'a Self::State<'b>, ($($c,)*): &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {
+                ($($x::get_column($x, $c, entity, row),)*)
+            }
+        }
+
+        impl<$($x: ParPattern),*> ParPattern for ($($x,)*) {
+            #[allow(non_snake_case)]
+            unsafe fn get_shared<'a, 'b: 'a>(($($x,)*): &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+                ($($x::get_shared($x, entity),)*)
+            }
         }
     };
 }
 
 impl_for_tuple!();
-impl_for_tuple!(A);
-impl_for_tuple!(A, B);
-impl_for_tuple!(A, B, C);
-impl_for_tuple!(A, B, C, D);
-impl_for_tuple!(A, B, C, D, E);
-impl_for_tuple!(A, B, C, D, E, F);
-impl_for_tuple!(A, B, C, D, E, F, G);
-impl_for_tuple!(A, B, C, D, E, F, G, H);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V, W);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V, W, X);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V, W, X, Y);
-impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V, W, X, Y, Z);
+impl_for_tuple!(A a);
+impl_for_tuple!(A a, B b);
+impl_for_tuple!(A a, B b, C c);
+impl_for_tuple!(A a, B b, C c, D d);
+impl_for_tuple!(A a, B b, C c, D d, E e);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w, X x);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w, X x, Y y);
+impl_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w, X x, Y y, Z z);
+
+macro_rules! impl_or_for_tuple {
+    ($($x:ident $c:ident),*) => {
+        impl<$($x: Pattern),*> Pattern for Or<($($x,)*)> {
+            // Each pattern's filter is kept to check entities against
+            type State<'a> = (Read<'a, Entities>, ($((Filter, $x::State<'a>),)*));
+            type Output<'a> = ($(Option<$x::Output<'a>>,)*);
+            // Only the patterns that match the archetype have columns in it
+            type Columns<'a> = ($(Option<$x::Columns<'a>>,)*);
+
+            fn comp_filter(ecs: &Ecs) -> Result<Filter, Error> {
+                // Entities must have at least one of the components required by the patterns, unless one of them
+                // requires none
+                let mut any = BitMask::zero();
+                let mut bounded = true;
+                $(let required = $x::comp_filter(ecs)?.required();
+                bounded &= required != BitMask::zero();
+                any |= required;)*
+                Ok(if bounded { Filter::any_of(any) } else { Filter::all() })
+            }
+
+            fn fetch_inner<'a>(ecs: &'a Ecs, ticks: Ticks) -> Result<Self::State<'a>, Error> {
+                Ok((ecs.try_read_resource()?, ($(($x::comp_filter(ecs)?, $x::fetch_inner(ecs, ticks)?),)*)))
+            }
+
+            fn access(access: &mut Access) {
+                access.read::<Entities>();
+                $($x::access(access);)*
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn matches<'b>((entities, ($($x,)*)): &Self::State<'b>, entity: EntityId) -> bool {
+                let mask = match entities.comp_mask(entity) {
+                    Some(mask) => mask,
+                    None => return false,
+                };
+                false $(|| (mask.matches(&$x.0) && $x::matches(&$x.1, entity)))*
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn get_unchecked<'a, 'b: 'a>((entities, ($($x,)*)): &'a mut Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+                let empty = BitMask::zero();
+                let mask = entities.comp_mask(entity).unwrap_or(&empty);
+                ($({
+                    let (filter, state) = $x;
+                    if mask.matches(filter) && $x::matches(state, entity) {
+                        Some($x::get_unchecked(state, entity))
+                    } else {
+                        None
+                    }
+                },)*)
+            }
+
+            #[allow(non_snake_case)]
+            fn columns<'a, 'b: 'a>((_, ($($x,)*)): &'a Self::State<'b>, archetype: usize, mask: &BitMask) -> Self::Columns<'a> {
+                ($(if mask.matches(&$x.0) {
+                    Some($x::columns(&$x.1, archetype, mask))
+                } else {
+                    None
+                },)*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn get_column<'a, 'b: 'a>((_, ($($x,)*)): &'a Self::State<'b>, ($($c,)*): &Self::Columns<'a>, entity: EntityId, row: usize) -> Self::Output<'a> {
+                ($(match $c {
+                    Some(columns) if $x::matches(&$x.1, entity) => Some($x::get_column(&$x.1, columns, entity, row)),
+                    _ => None,
+                },)*)
+            }
+        }
+
+        impl<$($x: ParPattern),*> ParPattern for Or<($($x,)*)> {
+            #[allow(non_snake_case)]
+            unsafe fn get_shared<'a, 'b: 'a>((entities, ($($x,)*)): &'a Self::State<'b>, entity: EntityId) -> Self::Output<'a> {
+                let empty = BitMask::zero();
+                let mask = entities.comp_mask(entity).unwrap_or(&empty);
+                ($({
+                    let (filter, state) = $x;
+                    if mask.matches(filter) && $x::matches(state, entity) {
+                        Some($x::get_shared(state, entity))
+                    } else {
+                        None
+                    }
+                },)*)
+            }
+        }
+    };
+}
+
+impl_or_for_tuple!(A a);
+impl_or_for_tuple!(A a, B b);
+impl_or_for_tuple!(A a, B b, C c);
+impl_or_for_tuple!(A a, B b, C c, D d);
+impl_or_for_tuple!(A a, B b, C c, D d, E e);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w, X x);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w, X x, Y y);
+impl_or_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k, L l, M m, O o, P p, Q q, R r, S s, T t, U u, V v, W w, X x, Y y, Z z);
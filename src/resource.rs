@@ -1,5 +1,17 @@
 use super::*;
 
-pub trait Resource: Any + Sized {}
+/// Bounds required of all data stored in the ECS: `Send + Sync` when the `parallel` feature is enabled.
+#[cfg(feature = "parallel")]
+pub trait Shareable: Send + Sync {}
+#[cfg(feature = "parallel")]
+impl<T: Send + Sync> Shareable for T {}
 
-impl<T: Any> Resource for T {}
+/// Bounds required of all data stored in the ECS: `Send + Sync` when the `parallel` feature is enabled.
+#[cfg(not(feature = "parallel"))]
+pub trait Shareable {}
+#[cfg(not(feature = "parallel"))]
+impl<T> Shareable for T {}
+
+pub trait Resource: Any + Sized + Shareable {}
+
+impl<T: Any + Shareable> Resource for T {}
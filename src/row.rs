@@ -1,38 +1,146 @@
-use std::{
-    cell::{RefCell, Ref, RefMut},
-    ops::{Deref, DerefMut},
-};
-
-#[derive(Default)]
-pub struct Row<T>(RefCell<T>);
-
-/// Read Or Write
-impl<T> Row<T> {
-    pub fn new(x: T) -> Self { Self(RefCell::new(x)) }
-    pub fn read(&self) -> Read<'_, T> { Read(self.0.borrow()) }
-    pub fn write(&self) -> Write<'_, T> { Write(self.0.borrow_mut()) }
-    pub fn get_mut(&mut self) -> &mut T { self.0.get_mut() }
-    pub fn into_inner(self) -> T { self.0.into_inner() }
-}
+#[cfg(not(feature = "parallel"))]
+pub use self::local::{Row, Read, Write};
+#[cfg(feature = "parallel")]
+pub use self::atomic::{Row, Read, Write};
 
-pub struct Read<'a, T>(Ref<'a, T>);
+#[cfg(not(feature = "parallel"))]
+mod local {
+    use std::{
+        cell::{RefCell, Ref, RefMut},
+        ops::{Deref, DerefMut},
+    };
 
-impl<'a, T> Clone for Read<'a, T> {
-    fn clone(&self) -> Self { Self(Ref::clone(&self.0)) }
-}
+    #[derive(Default)]
+    pub struct Row<T>(RefCell<T>);
 
-impl<'a, T> Deref for Read<'a, T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target { self.0.deref() }
-}
+    /// Read Or Write
+    impl<T> Row<T> {
+        pub fn new(x: T) -> Self { Self(RefCell::new(x)) }
+        pub fn read(&self) -> Read<'_, T> { Read(self.0.borrow()) }
+        pub fn write(&self) -> Write<'_, T> { Write(self.0.borrow_mut()) }
+        pub fn try_read(&self) -> Option<Read<'_, T>> { self.0.try_borrow().ok().map(Read) }
+        pub fn try_write(&self) -> Option<Write<'_, T>> { self.0.try_borrow_mut().ok().map(Write) }
+        pub fn get_mut(&mut self) -> &mut T { self.0.get_mut() }
+        pub fn into_inner(self) -> T { self.0.into_inner() }
+    }
+
+    pub struct Read<'a, T>(Ref<'a, T>);
+
+    impl<'a, T> Clone for Read<'a, T> {
+        fn clone(&self) -> Self { Self(Ref::clone(&self.0)) }
+    }
+
+    impl<'a, T> Deref for Read<'a, T> {
+        type Target = T;
+        fn deref(&self) -> &Self::Target { self.0.deref() }
+    }
 
-pub struct Write<'a, T>(RefMut<'a, T>);
+    pub struct Write<'a, T>(RefMut<'a, T>);
 
-impl<'a, T> Deref for Write<'a, T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target { self.0.deref() }
+    impl<'a, T> Deref for Write<'a, T> {
+        type Target = T;
+        fn deref(&self) -> &Self::Target { self.0.deref() }
+    }
+
+    impl<'a, T> DerefMut for Write<'a, T> {
+        fn deref_mut(&mut self) -> &mut Self::Target { self.0.deref_mut() }
+    }
 }
 
-impl<'a, T> DerefMut for Write<'a, T> {
-    fn deref_mut(&mut self) -> &mut Self::Target { self.0.deref_mut() }
+#[cfg(feature = "parallel")]
+mod atomic {
+    use std::{
+        cell::UnsafeCell,
+        ops::{Deref, DerefMut},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    // Set while the row is mutably borrowed. Readers that observe it back out again.
+    const WRITING: usize = !(usize::MAX >> 1);
+
+    /// Like a `RefCell`, but with an atomic borrow flag so that it may be shared between threads.
+    #[derive(Default)]
+    pub struct Row<T> {
+        borrow: AtomicUsize,
+        item: UnsafeCell<T>,
+    }
+
+    // Safety: access to the inner value is guarded by the borrow flag, with the same rules as `RwLock`
+    unsafe impl<T: Send> Send for Row<T> {}
+    unsafe impl<T: Send + Sync> Sync for Row<T> {}
+
+    /// Read Or Write
+    impl<T> Row<T> {
+        pub fn new(x: T) -> Self {
+            Self {
+                borrow: AtomicUsize::new(0),
+                item: UnsafeCell::new(x),
+            }
+        }
+
+        pub fn read(&self) -> Read<'_, T> { self.try_read().expect("already mutably borrowed") }
+        pub fn write(&self) -> Write<'_, T> { self.try_write().expect("already borrowed") }
+
+        pub fn try_read(&self) -> Option<Read<'_, T>> {
+            if self.borrow.fetch_add(1, Ordering::Acquire) & WRITING != 0 {
+                self.borrow.fetch_sub(1, Ordering::Release);
+                None
+            } else {
+                // Safety: the borrow flag guarantees that no writer exists
+                Some(Read { borrow: &self.borrow, item: unsafe { &*self.item.get() } })
+            }
+        }
+
+        pub fn try_write(&self) -> Option<Write<'_, T>> {
+            if self.borrow.compare_exchange(0, WRITING, Ordering::Acquire, Ordering::Relaxed).is_err() {
+                None
+            } else {
+                // Safety: the borrow flag guarantees that no other reader or writer exists
+                Some(Write { borrow: &self.borrow, item: unsafe { &mut *self.item.get() } })
+            }
+        }
+
+        pub fn get_mut(&mut self) -> &mut T { self.item.get_mut() }
+        pub fn into_inner(self) -> T { self.item.into_inner() }
+    }
+
+    pub struct Read<'a, T> {
+        borrow: &'a AtomicUsize,
+        item: &'a T,
+    }
+
+    impl<'a, T> Clone for Read<'a, T> {
+        fn clone(&self) -> Self {
+            self.borrow.fetch_add(1, Ordering::Acquire);
+            Self { borrow: self.borrow, item: self.item }
+        }
+    }
+
+    impl<'a, T> Deref for Read<'a, T> {
+        type Target = T;
+        fn deref(&self) -> &Self::Target { self.item }
+    }
+
+    impl<'a, T> Drop for Read<'a, T> {
+        fn drop(&mut self) { self.borrow.fetch_sub(1, Ordering::Release); }
+    }
+
+    pub struct Write<'a, T> {
+        borrow: &'a AtomicUsize,
+        item: &'a mut T,
+    }
+
+    impl<'a, T> Deref for Write<'a, T> {
+        type Target = T;
+        fn deref(&self) -> &Self::Target { self.item }
+    }
+
+    impl<'a, T> DerefMut for Write<'a, T> {
+        fn deref_mut(&mut self) -> &mut Self::Target { self.item }
+    }
+
+    impl<'a, T> Drop for Write<'a, T> {
+        // Subtract rather than store, since failed readers may be transiently holding a count
+        fn drop(&mut self) { self.borrow.fetch_sub(WRITING, Ordering::Release); }
+    }
 }
@@ -3,13 +3,26 @@ use super::*;
 pub use self::{
     null::NullStorage,
     vec::VecStorage,
+    sparse::SparseSetStorage,
+    hash::HashMapStorage,
+    btree::BTreeStorage,
 };
 
-use std::ops::{Deref, DerefMut};
+use crate::bitset::HiBitSet;
+use std::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+};
 
+/// Storage for a single type of component.
+///
+/// A storage owns its components, so any that remain when it is dropped must be dropped along with it. Its iterators
+/// are associated types rather than boxed trait objects, so iterating over a storage doesn't allocate.
 pub trait Storage<T: Component>: Resource + Default {
     type Ref<'a>: Deref<Target = T> where T: 'a;
     type RefMut<'a>: DerefMut<Target = T> where T: 'a;
+    type Iter<'a>: Iterator<Item = (EntityId, &'a T)> where Self: 'a, T: 'a;
+    type IterMut<'a>: Iterator<Item = (EntityId, &'a mut T)> where Self: 'a, T: 'a;
 
     /// Safety: The entity must have an already-inserted component in this storage.
     unsafe fn get_unchecked(&self, entity: EntityId) -> Self::Ref<'_>;
@@ -19,41 +32,171 @@ pub trait Storage<T: Component>: Resource + Default {
     unsafe fn insert_unchecked(&mut self, entity: EntityId, item: T);
     /// Safety: The entity must have an already-inserted component in this storage.
     unsafe fn remove_unchecked(&mut self, entity: EntityId) -> T;
+
+    /// Iterate over the entities in this storage and their components.
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Iterate mutably over the entities in this storage and their components.
+    ///
+    /// Unlike queries, this does not mark components as changed.
+    fn iter_mut(&mut self) -> Self::IterMut<'_>;
+
+    /// The number of components in this storage.
+    fn len(&self) -> usize { self.iter().count() }
+
+    fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Reserve space for at least `additional` more components.
+    fn reserve(&mut self, additional: usize) {}
+
+    /// Remove and drop all components in this storage.
+    ///
+    /// The storage of a component registered with an [`Ecs`] can only be read, see [`ComponentStorage`]. Use
+    /// [`Ecs::clear_comps`] instead.
+    fn clear(&mut self) {
+        let entities = self.iter().map(|(entity, _)| entity).collect::<Vec<_>>();
+        for entity in entities {
+            // Safety: the entity was yielded by `iter`, so it has a component
+            drop(unsafe { self.remove_unchecked(entity) });
+        }
+    }
+
+    /// Remove and drop all components for which `f` returns `false`.
+    ///
+    /// As with [`Storage::clear`], use [`Ecs::retain_comps`] for storages registered with an [`Ecs`].
+    fn retain(&mut self, mut f: impl FnMut(EntityId, &mut T) -> bool) {
+        let entities = self
+            .iter_mut()
+            .filter_map(|(entity, item)| if f(entity, item) { None } else { Some(entity) })
+            .collect::<Vec<_>>();
+        for entity in entities {
+            // Safety: as above
+            drop(unsafe { self.remove_unchecked(entity) });
+        }
+    }
+
+    /// The entities that have a component in this storage, if the storage keeps them packed together. Queries iterate
+    /// over the smallest of these rather than over every entity.
+    fn dense(&self) -> Option<&[EntityId]> { None }
+}
+
+/// A storage whose components can be borrowed mutably from several threads at once, so that [`Query::par_iter`] can
+/// yield them.
+///
+/// Safety: [`ParStorage::get_unchecked_shared`] must only borrow the component of the given entity, so that borrows of
+/// the components of different entities never alias.
+pub unsafe trait ParStorage<T: Component>: Storage<T> {
+    /// Like [`Storage::get_unchecked_mut`], but through a shared reference.
+    ///
+    /// Safety: As with [`Storage::get_unchecked_mut`], and the component must not be borrowed elsewhere while the
+    /// returned reference is in use.
+    unsafe fn get_unchecked_shared(&self, entity: EntityId) -> Self::RefMut<'_>;
+}
+
+/// The storage of component `C` in an [`Ecs`], which derefs to its [`Component::Storage`].
+///
+/// Only the `Ecs` may modify the storage, so that it stays in sync with the component masks of entities.
+pub struct ComponentStorage<C: Component> {
+    pub(crate) inner: C::Storage,
+}
+
+impl<C: Component> Default for ComponentStorage<C> {
+    fn default() -> Self { Self { inner: C::Storage::default() } }
+}
+
+impl<C: Component> Deref for ComponentStorage<C> {
+    type Target = C::Storage;
+
+    fn deref(&self) -> &Self::Target { &self.inner }
 }
 
 pub mod null {
     use super::*;
     use std::{
         marker::PhantomData,
-        mem::MaybeUninit,
+        mem,
+        ptr::NonNull,
     };
 
-    pub struct NullStorage<T>(PhantomData<T>);
+    /// Stores zero-sized components, taking no memory for the components themselves.
+    ///
+    /// Only the number of components is kept, so [`Storage::iter`] and [`Storage::iter_mut`] yield nothing; query the
+    /// [`Ecs`] for the entities that have the component instead. Using this storage for a type that is not zero-sized
+    /// is a compile-time error:
+    ///
+    /// ```compile_fail
+    /// # use synco::*;
+    /// struct Health(u32);
+    /// impl Component for Health { type Storage = NullStorage<Self>; }
+    ///
+    /// let ecs = Ecs::new().with_storage::<Health>();
+    /// ```
+    pub struct NullStorage<T> {
+        len: usize,
+        phantom: PhantomData<T>,
+    }
+
+    impl<T> NullStorage<T> {
+        const ZERO_SIZED: () = assert!(mem::size_of::<T>() == 0, "`NullStorage` can only store zero-sized types");
+
+        // Safety: `T` is zero-sized, so any well-aligned non-null pointer is valid for reads and writes of `T`
+        fn item() -> *mut T { NonNull::dangling().as_ptr() }
+    }
 
     impl<T> Default for NullStorage<T> {
-        fn default() -> Self { Self(PhantomData) }
+        fn default() -> Self {
+            let () = Self::ZERO_SIZED;
+            Self { len: 0, phantom: PhantomData }
+        }
+    }
+
+    impl<T> Drop for NullStorage<T> {
+        fn drop(&mut self) {
+            // Components are forgotten on insertion, so drop one for each present entity
+            for _ in 0..self.len {
+                unsafe { Self::item().drop_in_place() };
+            }
+        }
     }
 
     impl<T: Component> Storage<T> for NullStorage<T> {
         type Ref<'a> where T: 'a = &'a T;
         type RefMut<'a> where T: 'a = &'a mut T;
+        type Iter<'a> where Self: 'a, T: 'a = std::iter::Empty<(EntityId, &'a T)>;
+        type IterMut<'a> where Self: 'a, T: 'a = std::iter::Empty<(EntityId, &'a mut T)>;
 
-        unsafe fn get_unchecked(&self, entity: EntityId) -> Self::Ref<'_> {
-            assert_eq!(core::mem::size_of::<T>(), 0);
-            &*(&() as *const _ as *const _)
+        unsafe fn get_unchecked(&self, entity: EntityId) -> Self::Ref<'_> { &*Self::item() }
+
+        unsafe fn get_unchecked_mut(&mut self, entity: EntityId) -> Self::RefMut<'_> { &mut *Self::item() }
+
+        unsafe fn insert_unchecked(&mut self, entity: EntityId, item: T) {
+            let () = Self::ZERO_SIZED;
+            mem::forget(item);
+            self.len += 1;
         }
 
-        unsafe fn get_unchecked_mut(&mut self, entity: EntityId) -> Self::RefMut<'_> {
-            assert_eq!(core::mem::size_of::<T>(), 0);
-            &mut *(&mut () as *mut _ as *mut _)
+        unsafe fn remove_unchecked(&mut self, entity: EntityId) -> T {
+            self.len -= 1;
+            Self::item().read()
         }
 
-        unsafe fn insert_unchecked(&mut self, entity: EntityId, item: T) {}
+        fn iter(&self) -> Self::Iter<'_> { std::iter::empty() }
 
-        unsafe fn remove_unchecked(&mut self, entity: EntityId) -> T {
-            MaybeUninit::uninit().assume_init_read()
+        fn iter_mut(&mut self) -> Self::IterMut<'_> { std::iter::empty() }
+
+        fn len(&self) -> usize { self.len }
+
+        fn clear(&mut self) {
+            for _ in 0..self.len {
+                drop(unsafe { Self::item().read() });
+            }
+            self.len = 0;
         }
     }
+
+    unsafe impl<T: Component> ParStorage<T> for NullStorage<T> {
+        unsafe fn get_unchecked_shared(&self, entity: EntityId) -> Self::RefMut<'_> { &mut *Self::item() }
+    }
 }
 
 pub mod vec {
@@ -61,33 +204,318 @@ pub mod vec {
     use std::mem::MaybeUninit;
 
     pub struct VecStorage<T> {
-        items: Vec<MaybeUninit<T>>,
+        items: Vec<UnsafeCell<MaybeUninit<T>>>,
+        // The indices of the initialised slots of `items`
+        present: HiBitSet,
+        // The generation of the entity in each initialised slot
+        gens: Vec<u32>,
+        len: usize,
     }
 
     impl<T> Default for VecStorage<T> {
-        fn default() -> Self { Self { items: Vec::new() } }
+        fn default() -> Self { Self { items: Vec::new(), present: HiBitSet::default(), gens: Vec::new(), len: 0 } }
+    }
+
+    // Safety: components are only borrowed mutably through a shared reference by `get_unchecked_shared`, whose caller
+    // must not alias them
+    unsafe impl<T: Send + Sync> Sync for VecStorage<T> {}
+
+    impl<T> Drop for VecStorage<T> {
+        fn drop(&mut self) {
+            for idx in self.present.iter() {
+                // Safety: present slots are initialised
+                unsafe { self.items[idx].get_mut().assume_init_drop() };
+            }
+        }
     }
 
     impl<T: Component> Storage<T> for VecStorage<T> {
         type Ref<'a> where T: 'a = &'a T;
         type RefMut<'a> where T: 'a = &'a mut T;
+        type Iter<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a T)> + 'a;
+        type IterMut<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a mut T)> + 'a;
+
+        unsafe fn get_unchecked(&self, entity: EntityId) -> Self::Ref<'_> {
+            (*self.items.get_unchecked(entity.idx()).get()).assume_init_ref()
+        }
+
+        unsafe fn get_unchecked_mut(&mut self, entity: EntityId) -> Self::RefMut<'_> {
+            self.items.get_unchecked_mut(entity.idx()).get_mut().assume_init_mut()
+        }
+
+        unsafe fn insert_unchecked(&mut self, entity: EntityId, item: T) {
+            let idx = entity.idx();
+            if self.items.len() <= idx {
+                self.items.resize_with(idx + 1, || UnsafeCell::new(MaybeUninit::uninit()));
+                self.gens.resize(idx + 1, 0);
+            }
+            self.items.get_unchecked_mut(idx).get_mut().write(item);
+            *self.gens.get_unchecked_mut(idx) = entity.gen() as u32;
+            self.present.insert(idx);
+            self.len += 1;
+        }
+
+        unsafe fn remove_unchecked(&mut self, entity: EntityId) -> T {
+            let idx = entity.idx();
+            self.present.remove(idx);
+            self.len -= 1;
+            self.items.get_unchecked_mut(idx).get_mut().assume_init_read()
+        }
+
+        fn iter(&self) -> Self::Iter<'_> {
+            self.present
+                .iter()
+                // Safety: present slots are initialised
+                .map(move |idx| (EntityId::from_raw(idx, self.gens[idx]), unsafe { (*self.items[idx].get()).assume_init_ref() }))
+        }
+
+        fn iter_mut(&mut self) -> Self::IterMut<'_> {
+            let items = self.items.as_mut_ptr();
+            let gens = &self.gens;
+            self.present
+                .iter()
+                // Safety: present slots are initialised, and each is visited once
+                .map(move |idx| (EntityId::from_raw(idx, gens[idx]), unsafe { (*items.add(idx)).get_mut().assume_init_mut() }))
+        }
+
+        fn len(&self) -> usize { self.len }
+
+        fn reserve(&mut self, additional: usize) {
+            self.items.reserve(additional);
+            self.gens.reserve(additional);
+        }
+
+        fn clear(&mut self) {
+            for idx in self.present.iter() {
+                // Safety: present slots are initialised
+                unsafe { self.items[idx].get_mut().assume_init_drop() };
+            }
+            self.present.clear();
+            self.len = 0;
+        }
+    }
+
+    unsafe impl<T: Component> ParStorage<T> for VecStorage<T> {
+        unsafe fn get_unchecked_shared(&self, entity: EntityId) -> Self::RefMut<'_> {
+            (*self.items.get_unchecked(entity.idx()).get()).assume_init_mut()
+        }
+    }
+}
+
+pub mod sparse {
+    use super::*;
+
+    /// Stores components packed together, with a sparse index from entities into them.
+    ///
+    /// Unlike [`VecStorage`], memory use and query iteration scale with the number of entities that have the component
+    /// rather than the total number of entities.
+    pub struct SparseSetStorage<T> {
+        sparse: Vec<u32>,
+        dense: Vec<EntityId>,
+        items: Vec<UnsafeCell<T>>,
+    }
+
+    // Safety: as with `VecStorage`
+    unsafe impl<T: Send + Sync> Sync for SparseSetStorage<T> {}
+
+    impl<T> Default for SparseSetStorage<T> {
+        fn default() -> Self { Self { sparse: Vec::new(), dense: Vec::new(), items: Vec::new() } }
+    }
+
+    impl<T: Component> Storage<T> for SparseSetStorage<T> {
+        type Ref<'a> where T: 'a = &'a T;
+        type RefMut<'a> where T: 'a = &'a mut T;
+        type Iter<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a T)> + 'a;
+        type IterMut<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a mut T)> + 'a;
 
         unsafe fn get_unchecked(&self, entity: EntityId) -> Self::Ref<'_> {
-            self.items.get_unchecked(entity.idx()).assume_init_ref()
+            let idx = *self.sparse.get_unchecked(entity.idx()) as usize;
+            &*self.items.get_unchecked(idx).get()
         }
 
         unsafe fn get_unchecked_mut(&mut self, entity: EntityId) -> Self::RefMut<'_> {
-            self.items.get_unchecked_mut(entity.idx()).assume_init_mut()
+            let idx = *self.sparse.get_unchecked(entity.idx()) as usize;
+            self.items.get_unchecked_mut(idx).get_mut()
         }
 
         unsafe fn insert_unchecked(&mut self, entity: EntityId, item: T) {
             let idx = entity.idx();
-            self.items.resize_with(self.items.len().max(idx + 1), || MaybeUninit::uninit());
-            self.items.get_unchecked_mut(idx).write(item);
+            self.sparse.resize(self.sparse.len().max(idx + 1), 0);
+            *self.sparse.get_unchecked_mut(idx) = self.dense.len() as u32;
+            self.dense.push(entity);
+            self.items.push(UnsafeCell::new(item));
         }
 
         unsafe fn remove_unchecked(&mut self, entity: EntityId) -> T {
-            self.items.get_unchecked_mut(entity.idx()).assume_init_read()
+            let idx = *self.sparse.get_unchecked(entity.idx()) as usize;
+            self.dense.swap_remove(idx);
+            // The last item has been moved into the removed item's slot
+            if let Some(moved) = self.dense.get(idx) {
+                *self.sparse.get_unchecked_mut(moved.idx()) = idx as u32;
+            }
+            self.items.swap_remove(idx).into_inner()
+        }
+
+        fn iter(&self) -> Self::Iter<'_> {
+            // Safety: components are only mutated through a shared reference by queries, which borrow the storage uniquely
+            self.dense.iter().copied().zip(self.items.iter().map(|item| unsafe { &*item.get() }))
+        }
+
+        fn iter_mut(&mut self) -> Self::IterMut<'_> {
+            self.dense.iter().copied().zip(self.items.iter_mut().map(UnsafeCell::get_mut))
+        }
+
+        fn len(&self) -> usize { self.dense.len() }
+
+        fn reserve(&mut self, additional: usize) {
+            self.dense.reserve(additional);
+            self.items.reserve(additional);
+        }
+
+        fn clear(&mut self) {
+            self.dense.clear();
+            self.items.clear();
+        }
+
+        fn dense(&self) -> Option<&[EntityId]> { Some(&self.dense) }
+    }
+
+    unsafe impl<T: Component> ParStorage<T> for SparseSetStorage<T> {
+        unsafe fn get_unchecked_shared(&self, entity: EntityId) -> Self::RefMut<'_> {
+            let idx = *self.sparse.get_unchecked(entity.idx()) as usize;
+            &mut *self.items.get_unchecked(idx).get()
+        }
+    }
+}
+
+pub mod hash {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Stores components in a hash map keyed by entity index.
+    ///
+    /// Suited to components that only a handful of entities have.
+    pub struct HashMapStorage<T> {
+        items: HashMap<usize, (EntityId, UnsafeCell<T>)>,
+    }
+
+    // Safety: as with `VecStorage`
+    unsafe impl<T: Send + Sync> Sync for HashMapStorage<T> {}
+
+    impl<T> Default for HashMapStorage<T> {
+        fn default() -> Self { Self { items: HashMap::new() } }
+    }
+
+    impl<T: Component> Storage<T> for HashMapStorage<T> {
+        type Ref<'a> where T: 'a = &'a T;
+        type RefMut<'a> where T: 'a = &'a mut T;
+        type Iter<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a T)> + 'a;
+        type IterMut<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a mut T)> + 'a;
+
+        unsafe fn get_unchecked(&self, entity: EntityId) -> Self::Ref<'_> {
+            &*self.items.get(&entity.idx()).unwrap_unchecked().1.get()
+        }
+
+        unsafe fn get_unchecked_mut(&mut self, entity: EntityId) -> Self::RefMut<'_> {
+            self.items.get_mut(&entity.idx()).unwrap_unchecked().1.get_mut()
+        }
+
+        unsafe fn insert_unchecked(&mut self, entity: EntityId, item: T) {
+            self.items.insert(entity.idx(), (entity, UnsafeCell::new(item)));
+        }
+
+        unsafe fn remove_unchecked(&mut self, entity: EntityId) -> T {
+            self.items.remove(&entity.idx()).unwrap_unchecked().1.into_inner()
+        }
+
+        fn iter(&self) -> Self::Iter<'_> {
+            // Safety: as with `SparseSetStorage`
+            self.items.values().map(|(entity, item)| (*entity, unsafe { &*item.get() }))
+        }
+
+        fn iter_mut(&mut self) -> Self::IterMut<'_> {
+            self.items.values_mut().map(|(entity, item)| (*entity, item.get_mut()))
+        }
+
+        fn len(&self) -> usize { self.items.len() }
+
+        fn reserve(&mut self, additional: usize) { self.items.reserve(additional) }
+
+        fn clear(&mut self) { self.items.clear() }
+
+        fn retain(&mut self, mut f: impl FnMut(EntityId, &mut T) -> bool) {
+            self.items.retain(|_, (entity, item)| f(*entity, item.get_mut()))
+        }
+    }
+
+    unsafe impl<T: Component> ParStorage<T> for HashMapStorage<T> {
+        unsafe fn get_unchecked_shared(&self, entity: EntityId) -> Self::RefMut<'_> {
+            &mut *self.items.get(&entity.idx()).unwrap_unchecked().1.get()
+        }
+    }
+}
+
+pub mod btree {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// Stores components in a B-tree keyed by entity index.
+    ///
+    /// Like [`HashMapStorage`], but [`Storage::iter`] is always in order of entity index.
+    pub struct BTreeStorage<T> {
+        items: BTreeMap<usize, (EntityId, UnsafeCell<T>)>,
+    }
+
+    // Safety: as with `VecStorage`
+    unsafe impl<T: Send + Sync> Sync for BTreeStorage<T> {}
+
+    impl<T> Default for BTreeStorage<T> {
+        fn default() -> Self { Self { items: BTreeMap::new() } }
+    }
+
+    impl<T: Component> Storage<T> for BTreeStorage<T> {
+        type Ref<'a> where T: 'a = &'a T;
+        type RefMut<'a> where T: 'a = &'a mut T;
+        type Iter<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a T)> + 'a;
+        type IterMut<'a> where Self: 'a, T: 'a = impl Iterator<Item = (EntityId, &'a mut T)> + 'a;
+
+        unsafe fn get_unchecked(&self, entity: EntityId) -> Self::Ref<'_> {
+            &*self.items.get(&entity.idx()).unwrap_unchecked().1.get()
+        }
+
+        unsafe fn get_unchecked_mut(&mut self, entity: EntityId) -> Self::RefMut<'_> {
+            self.items.get_mut(&entity.idx()).unwrap_unchecked().1.get_mut()
+        }
+
+        unsafe fn insert_unchecked(&mut self, entity: EntityId, item: T) {
+            self.items.insert(entity.idx(), (entity, UnsafeCell::new(item)));
+        }
+
+        unsafe fn remove_unchecked(&mut self, entity: EntityId) -> T {
+            self.items.remove(&entity.idx()).unwrap_unchecked().1.into_inner()
+        }
+
+        fn iter(&self) -> Self::Iter<'_> {
+            // Safety: as with `SparseSetStorage`
+            self.items.values().map(|(entity, item)| (*entity, unsafe { &*item.get() }))
+        }
+
+        fn iter_mut(&mut self) -> Self::IterMut<'_> {
+            self.items.values_mut().map(|(entity, item)| (*entity, item.get_mut()))
+        }
+
+        fn len(&self) -> usize { self.items.len() }
+
+        fn clear(&mut self) { self.items.clear() }
+
+        fn retain(&mut self, mut f: impl FnMut(EntityId, &mut T) -> bool) {
+            self.items.retain(|_, (entity, item)| f(*entity, item.get_mut()))
+        }
+    }
+
+    unsafe impl<T: Component> ParStorage<T> for BTreeStorage<T> {
+        unsafe fn get_unchecked_shared(&self, entity: EntityId) -> Self::RefMut<'_> {
+            &mut *self.items.get(&entity.idx()).unwrap_unchecked().1.get()
         }
     }
 }
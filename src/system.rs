@@ -1,6 +1,10 @@
 use super::*;
 
-use std::marker::PhantomData;
+use std::{
+    any::TypeId,
+    marker::PhantomData,
+    sync::{Arc, Weak, atomic::{AtomicBool, AtomicU64, Ordering}},
+};
 
 pub trait System<'a> {
     type Input: Input<'a>;
@@ -9,27 +13,100 @@ pub trait System<'a> {
     fn run(self, inputs: Self::Input) -> Self::Output;
 }
 
-pub trait Input<'a> {
-    fn fetch(ecs: &'a Ecs) -> Self;
+/// The set of resources borrowed by a system input.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Vec<(TypeId, &'static str)>,
+    writes: Vec<(TypeId, &'static str)>,
+}
+
+impl Access {
+    pub fn read<R: Resource>(&mut self) {
+        self.reads.push((TypeId::of::<R>(), type_name::<R>()));
+    }
+
+    pub fn write<R: Resource>(&mut self) {
+        self.writes.push((TypeId::of::<R>(), type_name::<R>()));
+    }
+
+    /// Find a resource that is written by one access and borrowed by the other, if any.
+    pub fn conflict_with(&self, other: &Self) -> Option<&'static str> {
+        let find_write = |writes: &[(TypeId, &'static str)], other: &Self| writes
+            .iter()
+            .find(|(id, _)| other.reads
+                .iter()
+                .chain(other.writes.iter())
+                .any(|(other_id, _)| id == other_id))
+            .map(|(_, name)| *name);
+
+        find_write(&self.writes, other).or_else(|| find_write(&other.writes, self))
+    }
+
+    /// Find a resource that is written by this access and also borrowed elsewhere within it, if any.
+    pub fn self_conflict(&self) -> Option<&'static str> {
+        self.writes
+            .iter()
+            .enumerate()
+            .find(|(i, (id, _))| self.reads
+                .iter()
+                .chain(self.writes[i + 1..].iter())
+                .any(|(other_id, _)| id == other_id))
+            .map(|(_, (_, name))| *name)
+    }
+
+    pub(crate) fn of_input<'a, I: Input<'a>>() -> Self {
+        let mut access = Self::default();
+        I::access(&mut access);
+        access
+    }
+
+    pub(crate) fn of_pattern<P: Pattern>() -> Self {
+        let mut access = Self::default();
+        P::access(&mut access);
+        access
+    }
+}
+
+pub trait Input<'a>: Sized {
+    fn try_fetch(ecs: &'a Ecs, ticks: Ticks) -> Result<Self, Error>;
+
+    fn fetch(ecs: &'a Ecs, ticks: Ticks) -> Self {
+        Self::try_fetch(ecs, ticks).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Declare the resources that [`Input::fetch`] will borrow.
+    fn access(access: &mut Access);
 }
 
 impl<'a, R: Resource> Input<'a> for Read<'a, R> {
-    fn fetch(ecs: &'a Ecs) -> Self { ecs.read_resource() }
+    fn try_fetch(ecs: &'a Ecs, ticks: Ticks) -> Result<Self, Error> { ecs.try_read_resource() }
+    fn access(access: &mut Access) { access.read::<R>() }
 }
 
 impl<'a, R: Resource> Input<'a> for Write<'a, R> {
-    fn fetch(ecs: &'a Ecs) -> Self { ecs.write_resource() }
+    fn try_fetch(ecs: &'a Ecs, ticks: Ticks) -> Result<Self, Error> { ecs.try_write_resource() }
+    fn access(access: &mut Access) { access.write::<R>() }
 }
 
 impl<'a, P: Pattern> Input<'a> for Query<'a, P> {
-    fn fetch(ecs: &'a Ecs) -> Self { P::fetch(ecs) }
+    fn try_fetch(ecs: &'a Ecs, ticks: Ticks) -> Result<Self, Error> { P::try_fetch(ecs, ticks) }
+
+    fn access(access: &mut Access) {
+        access.read::<Entities>();
+        access.read::<archetype::Archetypes>();
+        P::access(access);
+    }
 }
 
 macro_rules! impl_for_tuple {
     ($($x:ident),*) => {
         impl<'a, $($x: Input<'a>),*> Input<'a> for ($($x,)*) {
-            fn fetch(ecs: &'a Ecs) -> Self {
-                ($($x::fetch(ecs),)*)
+            fn try_fetch(ecs: &'a Ecs, ticks: Ticks) -> Result<Self, Error> {
+                Ok(($($x::try_fetch(ecs, ticks)?,)*))
+            }
+
+            fn access(access: &mut Access) {
+                $($x::access(access);)*
             }
         }
     };
@@ -62,6 +139,31 @@ impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V, W
 impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V, W, X, Y);
 impl_for_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, O, P, Q, R, S, T, U, V, W, X, Y, Z);
 
+/// Remembers when a system last ran, so that [`Added`], [`Changed`] and [`RemovedComponents`] only observe the
+/// changes made since. Keep one for each system and pass it to [`Ecs::run_with_state`] whenever the system runs.
+///
+/// A state should only be used with a single [`Ecs`]. Removals are kept until every live state has seen them.
+#[derive(Debug, Default)]
+pub struct SystemState {
+    last_run: Arc<AtomicU64>,
+    registered: AtomicBool,
+}
+
+impl SystemState {
+    pub fn new() -> Self { Self::default() }
+
+    pub(crate) fn last_run(&self) -> u64 { self.last_run.load(Ordering::Relaxed) }
+
+    pub(crate) fn set_last_run(&self, ecs: &Ecs, tick: u64) {
+        self.last_run.store(tick, Ordering::Relaxed);
+        if !self.registered.swap(true, Ordering::Relaxed) {
+            ecs.register_state(Arc::downgrade(&self.last_run));
+        }
+    }
+}
+
+pub(crate) type StateTick = Weak<AtomicU64>;
+
 pub struct FnSystem<F, P, O>(F, PhantomData<(P, O)>);
 
 impl<'a, F: FnOnce(P) -> O, P: Input<'a>, O> System<'a> for FnSystem<F, P, O>
@@ -0,0 +1,164 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote,
+    Data, DeriveInput, Error, Fields, Index, Member, Type,
+};
+
+// The storages provided by synco, which are generic over only the component type
+const GENERIC_STORAGES: &[&str] = &["VecStorage", "NullStorage", "SparseSetStorage", "HashMapStorage", "BTreeStorage"];
+
+/// Implement `Component` for a type.
+///
+/// The storage defaults to `VecStorage`, and may be picked with `#[storage(NullStorage)]`. Storages other than those
+/// provided by synco must be given in full, such as `#[storage(MyStorage<Self>)]`.
+#[proc_macro_derive(Component, attributes(storage))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    component(input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Implement `Bundle` for a struct whose fields are all components.
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    bundle(input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Implement `Input` for a struct with named fields that are all system inputs, such that it may be used as a system
+/// parameter.
+///
+/// The struct must have a lifetime parameter, which is used as the lifetime of the fetch.
+#[proc_macro_derive(Input)]
+pub fn derive_input(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    system_input(input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+fn component(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut storage = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("storage")) {
+        if storage.is_some() {
+            return Err(Error::new_spanned(attr, "duplicate `storage` attribute"));
+        }
+        let mut ty: Type = attr.parse_args()?;
+        // Allow `#[storage(NullStorage)]` as a shorthand for `#[storage(NullStorage<Self>)]`. Other storages may not be
+        // generic, so they must be given in full.
+        if let Type::Path(path) = &mut ty {
+            if let Some(last) = path.path.segments.last_mut() {
+                if last.arguments.is_empty() && GENERIC_STORAGES.iter().any(|name| last.ident == name) {
+                    last.arguments = syn::PathArguments::AngleBracketed(parse_quote!(<Self>));
+                }
+            }
+        }
+        storage = Some(ty);
+    }
+    let storage = storage.map(|ty| quote!(type Storage = #ty;));
+
+    Ok(quote! {
+        impl #impl_generics ::synco::Component for #name #ty_generics #where_clause {
+            #storage
+        }
+    })
+}
+
+fn fields(input: &DeriveInput, named_only: bool) -> Result<Vec<(Member, Type)>, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(Error::new_spanned(input, "expected a struct")),
+    };
+    if named_only && !matches!(fields, Fields::Named(_)) {
+        return Err(Error::new_spanned(input, "expected a struct with named fields"));
+    }
+    Ok(fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(i)),
+            };
+            (member, field.ty.clone())
+        })
+        .collect())
+}
+
+fn bundle(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (members, tys): (Vec<_>, Vec<_>) = fields(&input, false)?.into_iter().unzip();
+
+    // Delegate to the tuple of the fields
+    let tuple = quote!(<(#(#tys,)*) as ::synco::Bundle>);
+
+    Ok(quote! {
+        impl #impl_generics ::synco::Bundle for #name #ty_generics #where_clause {
+            fn comp_mask(ecs: &::synco::Ecs) -> ::core::result::Result<::synco::BitMask, ::synco::Error> {
+                #tuple::comp_mask(ecs)
+            }
+
+            fn on_insert(ecs: &mut ::synco::Ecs, entity: ::synco::EntityId, mask: &::synco::BitMask) {
+                #tuple::on_insert(ecs, entity, mask)
+            }
+
+            fn on_replace(ecs: &mut ::synco::Ecs, entity: ::synco::EntityId, mask: &::synco::BitMask) {
+                #tuple::on_replace(ecs, entity, mask)
+            }
+
+            fn on_remove(ecs: &mut ::synco::Ecs, entity: ::synco::EntityId, mask: &::synco::BitMask) {
+                #tuple::on_remove(ecs, entity, mask)
+            }
+
+            unsafe fn insert_unchecked(
+                self,
+                ecs: &mut ::synco::Ecs,
+                entity: ::synco::EntityId,
+                old_mask: &::synco::BitMask,
+                tick: u64,
+            ) {
+                #tuple::insert_unchecked((#(self.#members,)*), ecs, entity, old_mask, tick)
+            }
+
+            unsafe fn remove_unchecked(
+                ecs: &mut ::synco::Ecs,
+                entity: ::synco::EntityId,
+                old_mask: &::synco::BitMask,
+                tick: u64,
+            ) {
+                #tuple::remove_unchecked(ecs, entity, old_mask, tick)
+            }
+        }
+    })
+}
+
+fn system_input(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (members, tys): (Vec<_>, Vec<_>) = fields(&input, true)?.into_iter().unzip();
+
+    let lifetime = match input.generics.lifetimes().next() {
+        Some(param) => &param.lifetime,
+        None => return Err(Error::new_spanned(&input.ident, "expected a lifetime parameter")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::synco::Input<#lifetime> for #name #ty_generics #where_clause {
+            fn try_fetch(
+                ecs: &#lifetime ::synco::Ecs,
+                ticks: ::synco::Ticks,
+            ) -> ::core::result::Result<Self, ::synco::Error> {
+                ::core::result::Result::Ok(Self {
+                    #(#members: <#tys as ::synco::Input<#lifetime>>::try_fetch(ecs, ticks)?,)*
+                })
+            }
+
+            fn access(access: &mut ::synco::Access) {
+                #(<#tys as ::synco::Input<#lifetime>>::access(access);)*
+            }
+        }
+    })
+}
@@ -0,0 +1,85 @@
+use synco::*;
+
+#[derive(Debug, PartialEq)]
+struct A(u32);
+impl Component for A {}
+
+#[derive(Debug, PartialEq)]
+struct B(u32);
+impl Component for B {}
+
+struct World {
+    ecs: Ecs,
+    only_a: EntityId,
+    only_b: EntityId,
+    both: EntityId,
+    neither: EntityId,
+}
+
+fn world(layout: Layout) -> World {
+    let mut ecs = Ecs::new_with_layout(layout)
+        .with_storage::<A>()
+        .with_storage::<B>();
+    let only_a = ecs.spawn((A(1),));
+    let only_b = ecs.spawn((B(2),));
+    let both = ecs.spawn((A(3), B(4)));
+    let neither = ecs.create().finish();
+    World { ecs, only_a, only_b, both, neither }
+}
+
+fn or(layout: Layout) {
+    let World { ecs, only_a, only_b, both, neither } = world(layout);
+
+    let mut q = ecs.query::<Or<(&A, &B)>>();
+    assert_eq!(q.get(only_a), Some((Some(&A(1)), None)));
+    assert_eq!(q.get(only_b), Some((None, Some(&B(2)))));
+    assert_eq!(q.get(both), Some((Some(&A(3)), Some(&B(4)))));
+    assert_eq!(q.get(neither), None);
+    assert_eq!(q.iter().count(), 3);
+    drop(q);
+
+    // Matches entities that don't have an `A`, or that have a `B`
+    let mut q = ecs.query::<(EntityId, Or<(Not<A>, &B)>)>();
+    assert_eq!(q.get(only_a), None);
+    assert_eq!(q.get(only_b), Some((only_b, (Some(()), Some(&B(2))))));
+    assert_eq!(q.get(both), Some((both, (None, Some(&B(4))))));
+    assert_eq!(q.get(neither), Some((neither, (Some(()), None))));
+    let mut all = q.iter().map(|(entity, _)| entity).collect::<Vec<_>>();
+    all.sort_by_key(|entity| [only_b, both, neither].iter().position(|e| e == entity));
+    assert_eq!(all, [only_b, both, neither]);
+    drop(q);
+
+    let mut q = ecs.query::<AnyOf<(&mut A, With<B>)>>();
+    q.iter().for_each(|(a, _)| if let Some(a) = a { a.0 += 10 });
+    drop(q);
+    assert_eq!(ecs.query::<&A>().iter().map(|a| a.0).sum::<u32>(), 24);
+}
+
+fn with(layout: Layout) {
+    let World { ecs, only_a, only_b, both, .. } = world(layout);
+
+    // `With` doesn't borrow the storage, so this can run while `A` is borrowed mutably
+    let mut a = ecs.query::<&mut A>();
+    let mut q = ecs.query::<(With<A>, &mut B)>();
+    q.iter().for_each(|((), b)| b.0 += 10);
+    assert_eq!(q.get(only_b), None);
+    assert_eq!(q.get(both), Some(((), &mut B(14))));
+    drop(q);
+    assert_eq!(a.get(only_a), Some(&mut A(1)));
+    drop(a);
+
+    let sys = |_: Query<&mut A>, mut q: Query<(With<A>, &B)>| q.iter().count();
+    assert_eq!(ecs.try_run(sys), Ok(1));
+}
+
+#[test]
+fn or_storage() { or(Layout::Storage) }
+
+#[test]
+fn or_archetype() { or(Layout::Archetype) }
+
+#[test]
+fn with_storage() { with(Layout::Storage) }
+
+#[test]
+fn with_archetype() { with(Layout::Archetype) }
@@ -0,0 +1,62 @@
+use synco::*;
+
+#[derive(Debug, PartialEq)]
+struct Pos(u32);
+impl Component for Pos {}
+
+#[derive(Debug, PartialEq)]
+struct Vel(u32);
+impl Component for Vel {}
+
+fn run(layout: Layout) {
+    let mut ecs = Ecs::new_with_layout(layout)
+        .with_storage::<Pos>()
+        .with_storage::<Vel>();
+    let a = ecs.spawn((Pos(0),));
+    let b = ecs.spawn((Pos(0),));
+    let c = ecs.spawn((Pos(0),));
+
+    let (created, empty) = ecs.run(|mut commands: Commands| {
+        let created = commands.create();
+        commands.insert_comp(created, Pos(1));
+        commands.insert_comp(created, Vel(1));
+        commands.remove_comp::<Pos>(created);
+
+        commands.remove_comp::<Pos>(a);
+        commands.insert_comp(a, Vel(2));
+
+        // Deleted by an earlier command
+        commands.delete(b);
+        commands.insert_comp(b, Vel(3));
+
+        // Deleted before the commands are applied
+        commands.insert_comp(c, Vel(4));
+        commands.remove_comp::<Pos>(c);
+
+        (created, commands.create())
+    });
+
+    // Nothing changes until the commands are applied
+    assert_eq!(ecs.query::<&Vel>().iter().count(), 0);
+    ecs.delete(c);
+    ecs.apply_commands();
+
+    let mut q = ecs.query::<(Maybe<Pos>, Maybe<Vel>)>();
+    assert_eq!(q.get(a), Some((None, Some(&Vel(2)))));
+    assert_eq!(q.get(created), Some((None, Some(&Vel(1)))));
+    assert_eq!(q.get(empty), Some((None, None)));
+    assert_eq!(q.get(b), None);
+    assert_eq!(q.get(c), None);
+    assert_eq!(q.iter().count(), 3);
+    drop(q);
+
+    // The queue has been emptied
+    ecs.apply_commands();
+    assert_eq!(ecs.query::<&Vel>().iter().count(), 2);
+}
+
+#[test]
+fn commands_storage() { run(Layout::Storage) }
+
+#[test]
+fn commands_archetype() { run(Layout::Archetype) }
@@ -0,0 +1,78 @@
+#![cfg(feature = "derive")]
+
+use synco::{Ecs, EntityId, Query, Read, Maybe, storage::{Storage, ComponentStorage, SparseSetStorage}};
+
+#[derive(synco::Component, Debug, PartialEq)]
+struct Pos(u32);
+
+#[derive(synco::Component, Debug, PartialEq)]
+#[storage(SparseSetStorage)]
+struct Vel(u32);
+
+#[derive(synco::Component, Debug, PartialEq)]
+#[storage(synco::NullStorage)]
+struct Frozen;
+
+// A storage named without generic arguments must be used as written
+type NameStorage = SparseSetStorage<Name>;
+
+#[derive(synco::Component, Debug, PartialEq)]
+#[storage(NameStorage)]
+struct Name(&'static str);
+
+#[derive(synco::Component, Debug, PartialEq)]
+#[storage(synco::HashMapStorage<Self>)]
+struct Health(u32);
+
+#[derive(synco::Bundle)]
+struct Body {
+    pos: Pos,
+    vel: Vel,
+}
+
+#[derive(synco::Bundle)]
+struct Tagged(Name, Frozen);
+
+#[derive(synco::Input)]
+struct Movement<'a> {
+    bodies: Query<'a, (&'static mut Pos, &'static Vel)>,
+    names: Read<'a, ComponentStorage<Name>>,
+}
+
+fn movement(mut input: Movement) -> usize {
+    for (pos, vel) in input.bodies.iter() {
+        pos.0 += vel.0;
+    }
+    input.names.len()
+}
+
+#[test]
+fn derives() {
+    fn storage_of<C: synco::Component>() -> &'static str { core::any::type_name::<C::Storage>() }
+    assert!(storage_of::<Pos>().contains("VecStorage"));
+    assert!(storage_of::<Vel>().contains("SparseSetStorage"));
+    assert!(storage_of::<Frozen>().contains("NullStorage"));
+    assert!(storage_of::<Name>().contains("SparseSetStorage"));
+    assert!(storage_of::<Health>().contains("HashMapStorage"));
+
+    let mut ecs = Ecs::new()
+        .with_storage::<Pos>()
+        .with_storage::<Vel>()
+        .with_storage::<Frozen>()
+        .with_storage::<Name>()
+        .with_storage::<Health>();
+
+    let a = ecs.spawn(Body { pos: Pos(1), vel: Vel(2) });
+    let b = ecs.spawn(Tagged(Name("b"), Frozen));
+    ecs.insert_bundle(b, Body { pos: Pos(10), vel: Vel(0) });
+    ecs.insert_comp(a, Health(3));
+
+    assert_eq!(ecs.run(movement), 1);
+    assert_eq!(ecs.query::<&Pos>().get(a), Some(&Pos(3)));
+    assert_eq!(ecs.query::<(&Pos, &Name, Maybe<Frozen>)>().get(b).map(|(p, n, f)| (p.0, n.0, f.is_some())), Some((10, "b", true)));
+
+    ecs.remove_bundle::<Body>(b);
+    assert_eq!(ecs.query::<EntityId>().iter().filter(|e| *e == b).count(), 1);
+    assert!(ecs.query::<&Pos>().get(b).is_none());
+    assert_eq!(ecs.query::<&Health>().get(a), Some(&Health(3)));
+}
@@ -0,0 +1,101 @@
+use synco::*;
+use std::sync::{Arc, Mutex};
+
+struct A(u32);
+impl Component for A {}
+
+struct B(u32);
+impl Component for B {}
+
+// What each system saw, in the order that they ran
+type Log = Arc<Mutex<Vec<(&'static str, u32)>>>;
+
+#[derive(Clone)]
+struct ReadA(Log);
+
+impl<'a> System<'a> for ReadA {
+    type Input = Query<'a, &'a A>;
+
+    fn run(self, mut q: Self::Input) {
+        let a = q.iter().map(|a| a.0).sum();
+        self.0.lock().unwrap().push(("read a", a));
+    }
+}
+
+#[derive(Clone)]
+struct ReadB(Log);
+
+impl<'a> System<'a> for ReadB {
+    type Input = Query<'a, &'a B>;
+
+    fn run(self, mut q: Self::Input) {
+        let b = q.iter().map(|b| b.0).sum();
+        self.0.lock().unwrap().push(("read b", b));
+    }
+}
+
+#[derive(Clone)]
+struct DoubleA(Log);
+
+impl<'a> System<'a> for DoubleA {
+    type Input = Query<'a, &'a mut A>;
+
+    fn run(self, mut q: Self::Input) {
+        q.iter().for_each(|a| a.0 *= 2);
+        self.0.lock().unwrap().push(("double a", q.iter().map(|a| a.0).sum()));
+    }
+}
+
+#[derive(Clone)]
+struct IncrementA(Log);
+
+impl<'a> System<'a> for IncrementA {
+    type Input = Query<'a, &'a mut A>;
+
+    fn run(self, mut q: Self::Input) {
+        q.iter().for_each(|a| a.0 += 1);
+        self.0.lock().unwrap().push(("increment a", q.iter().map(|a| a.0).sum()));
+    }
+}
+
+fn name_of<T>(_: &T) -> &'static str { core::any::type_name::<T>() }
+
+#[test]
+fn stages() {
+    let log = Log::default();
+    let (read_a, read_b, double_a, increment_a) = (ReadA(log.clone()), ReadB(log.clone()), DoubleA(log.clone()), IncrementA(log.clone()));
+    let dispatcher = Dispatcher::new()
+        .with(read_a.clone())
+        .with(double_a.clone())
+        .with(increment_a.clone())
+        .with(read_b.clone());
+
+    // Conflicting systems are staged in the order they were added, others join the earliest stage they can
+    assert_eq!(dispatcher.stages().collect::<Vec<_>>(), [
+        vec![name_of(&read_a), name_of(&read_b)],
+        vec![name_of(&double_a)],
+        vec![name_of(&increment_a)],
+    ]);
+}
+
+#[test]
+fn run() {
+    let mut ecs = Ecs::new()
+        .with_storage::<A>()
+        .with_storage::<B>();
+    ecs.spawn((A(1), B(5)));
+
+    let log = Log::default();
+    let dispatcher = Dispatcher::new()
+        .with(ReadA(log.clone()))
+        .with(DoubleA(log.clone()))
+        .with(IncrementA(log.clone()))
+        .with(ReadB(log.clone()));
+
+    dispatcher.run(&ecs);
+    let mut log = std::mem::take(&mut *log.lock().unwrap());
+    // The first stage may run in either order when the systems run in parallel
+    log[..2].sort();
+    assert_eq!(log, [("read a", 1), ("read b", 5), ("double a", 2), ("increment a", 3)]);
+    assert_eq!(ecs.query::<&A>().iter().next().unwrap().0, 3);
+}
@@ -0,0 +1,65 @@
+use synco::*;
+use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+macro_rules! counted {
+    ($($name:ident: $storage:ident),*) => {$(
+        // Counts its drops, so that each test has its own counter
+        struct $name(Arc<AtomicUsize>);
+
+        impl Drop for $name {
+            fn drop(&mut self) { self.0.fetch_add(1, Ordering::SeqCst); }
+        }
+
+        impl Component for $name { type Storage = $storage<Self>; }
+
+        impl From<Arc<AtomicUsize>> for $name {
+            fn from(drops: Arc<AtomicUsize>) -> Self { Self(drops) }
+        }
+    )*};
+}
+
+counted!(InVec: VecStorage, InSparseSet: SparseSetStorage, InHashMap: HashMapStorage, InBTree: BTreeStorage);
+
+fn check<C: Component + From<Arc<AtomicUsize>>>(layout: Layout) {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let count = || drops.load(Ordering::SeqCst);
+
+    let mut ecs = Ecs::new_with_layout(layout).with_storage::<C>();
+    let es = (0..4)
+        .map(|_| ecs.create().with(C::from(drops.clone())).finish())
+        .collect::<Vec<_>>();
+    assert_eq!(count(), 0);
+
+    ecs.delete(es[0]);
+    assert_eq!(count(), 1);
+
+    // Removed and replaced components are handed back rather than dropped
+    let removed = ecs.remove_comp::<C>(es[1]);
+    assert_eq!(count(), 1);
+    drop(removed);
+    assert_eq!(count(), 2);
+
+    let replaced = ecs.insert_comp(es[2], C::from(drops.clone()));
+    assert_eq!(count(), 2);
+    drop(replaced);
+    assert_eq!(count(), 3);
+
+    // The new component of `es[2]`, and that of `es[3]`
+    drop(ecs);
+    assert_eq!(count(), 5);
+}
+
+#[test]
+fn vec_storage() { check::<InVec>(Layout::Storage) }
+
+#[test]
+fn sparse_set_storage() { check::<InSparseSet>(Layout::Storage) }
+
+#[test]
+fn hash_map_storage() { check::<InHashMap>(Layout::Storage) }
+
+#[test]
+fn btree_storage() { check::<InBTree>(Layout::Storage) }
+
+#[test]
+fn archetype() { check::<InVec>(Layout::Archetype) }
@@ -0,0 +1,85 @@
+use synco::*;
+
+struct A;
+impl Component for A {}
+
+struct B;
+impl Component for B {}
+
+#[test]
+fn incompatible_pattern() {
+    let ecs = Ecs::new()
+        .with_storage::<A>()
+        .with_storage::<B>();
+
+    assert_eq!(
+        ecs.try_query::<(&A, Not<A>)>().err(),
+        Some(Error::IncompatiblePattern(core::any::type_name::<(&A, Not<A>)>())),
+    );
+    assert!(matches!(ecs.try_query::<(With<B>, (&A, Not<B>))>(), Err(Error::IncompatiblePattern(_))));
+    assert!(matches!(ecs.try_query::<(Or<(&A, &B)>, Not<A>, Not<B>)>(), Err(Error::IncompatiblePattern(_))));
+    assert!(ecs.try_query::<(&A, Not<B>)>().is_ok());
+}
+
+#[test]
+#[should_panic]
+fn incompatible_pattern_panics() {
+    let ecs = Ecs::new().with_storage::<A>();
+    ecs.query::<(&A, Not<A>)>();
+}
+
+#[test]
+fn duplicate_component() {
+    let mut ecs = Ecs::new()
+        .with_storage::<A>()
+        .with_storage::<B>();
+    let entity = ecs.create().finish();
+
+    let err = Error::DuplicateComponent(core::any::type_name::<A>());
+    assert_eq!(ecs.try_insert_bundle(entity, (A, B, A)), Err(err.clone()));
+    assert_eq!(ecs.try_remove_bundle::<(A, A)>(entity), Err(err));
+    assert_eq!(ecs.query::<(&A, &B)>().iter().count(), 0);
+
+    assert_eq!(ecs.try_insert_bundle(entity, (A, B)), Ok(()));
+    assert_eq!(ecs.query::<(&A, &B)>().iter().count(), 1);
+}
+
+#[test]
+#[should_panic]
+fn duplicate_component_panics() {
+    let mut ecs = Ecs::new().with_storage::<A>();
+    ecs.spawn((A, A));
+}
+
+#[test]
+fn system_conflict() {
+    let ecs = Ecs::new().with_storage::<A>();
+
+    fn conflicting(_: Query<&A>, _: Query<&mut A>) {}
+    fn name_of<T>(_: &T) -> &'static str { core::any::type_name::<T>() }
+    assert_eq!(
+        ecs.try_run(conflicting),
+        Err(Error::SystemConflict {
+            system: name_of(&conflicting),
+            resource: core::any::type_name::<ComponentStorage<A>>(),
+        }),
+    );
+    assert!(matches!(ecs.try_run(|_: Query<&mut A>, _: Query<&mut A>| ()), Err(Error::SystemConflict { .. })));
+    assert_eq!(ecs.try_run(|_: Query<&A>, _: Query<&A>| ()), Ok(()));
+}
+
+#[test]
+fn invalid_spawn() {
+    struct Unregistered;
+    impl Component for Unregistered {}
+
+    let mut ecs = Ecs::new().with_storage::<A>();
+
+    assert_eq!(ecs.try_spawn((A, A)), Err(Error::DuplicateComponent(core::any::type_name::<A>())));
+    assert_eq!(ecs.try_spawn((A, Unregistered)), Err(Error::MissingStorage(core::any::type_name::<Unregistered>())));
+    // No entity is created for an invalid bundle
+    assert_eq!(ecs.query::<EntityId>().iter().count(), 0);
+
+    let entity = ecs.try_spawn((A,)).unwrap();
+    assert_eq!(ecs.query::<EntityId>().iter().collect::<Vec<_>>(), [entity]);
+}
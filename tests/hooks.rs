@@ -0,0 +1,69 @@
+use synco::*;
+
+// Each hook call, along with the entity's `Hp` at the time
+#[derive(Default)]
+struct Events(Vec<(&'static str, EntityId, Option<u32>)>);
+
+struct Hp(u32);
+
+impl Component for Hp {
+    fn on_insert(ecs: &mut Ecs, entity: EntityId) { log(ecs, "insert", entity) }
+
+    fn on_replace(ecs: &mut Ecs, entity: EntityId) { log(ecs, "replace", entity) }
+
+    fn on_remove(ecs: &mut Ecs, entity: EntityId) { log(ecs, "remove", entity) }
+}
+
+struct Name;
+impl Component for Name {}
+
+fn log(ecs: &mut Ecs, event: &'static str, entity: EntityId) {
+    let hp = ecs.query::<&Hp>().get(entity).map(|hp| hp.0);
+    ecs.mut_resource::<Events>().0.push((event, entity, hp));
+}
+
+fn take(ecs: &mut Ecs) -> Vec<(&'static str, EntityId, Option<u32>)> {
+    std::mem::take(&mut ecs.mut_resource::<Events>().0)
+}
+
+fn run(layout: Layout) {
+    let mut ecs = Ecs::new_with_layout(layout)
+        .with_storage::<Hp>()
+        .with_storage::<Name>()
+        .with_resource(Events::default());
+
+    let a = ecs.create().finish();
+    ecs.insert_comp(a, Hp(1));
+    assert_eq!(take(&mut ecs), [("insert", a, Some(1))]);
+
+    // Replacing sees the old component, and doesn't count as an insertion
+    ecs.insert_comp(a, Hp(2));
+    assert_eq!(take(&mut ecs), [("replace", a, Some(1))]);
+
+    ecs.remove_comp::<Hp>(a);
+    assert_eq!(take(&mut ecs), [("remove", a, Some(2))]);
+    ecs.remove_comp::<Hp>(a);
+    assert_eq!(take(&mut ecs), []);
+
+    ecs.insert_comp(a, Hp(3));
+    ecs.delete(a);
+    assert_eq!(take(&mut ecs), [("insert", a, Some(3)), ("remove", a, Some(3))]);
+
+    let b = ecs.spawn((Name, Hp(4)));
+    assert_eq!(take(&mut ecs), [("insert", b, Some(4))]);
+    ecs.insert_bundle(b, (Hp(5), Name));
+    assert_eq!(take(&mut ecs), [("replace", b, Some(4))]);
+    ecs.remove_bundle::<(Name, Hp)>(b);
+    assert_eq!(take(&mut ecs), [("remove", b, Some(5))]);
+
+    // Other components don't trigger the hooks
+    let c = ecs.spawn((Name,));
+    ecs.delete(c);
+    assert_eq!(take(&mut ecs), []);
+}
+
+#[test]
+fn hooks_storage() { run(Layout::Storage) }
+
+#[test]
+fn hooks_archetype() { run(Layout::Archetype) }
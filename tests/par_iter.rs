@@ -0,0 +1,47 @@
+#![cfg(feature = "rayon")]
+
+use synco::{*, rayon::prelude::*};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+struct Pos(u64);
+impl Component for Pos {}
+
+struct Vel(u64);
+impl Component for Vel {}
+
+struct Rare;
+impl Component for Rare { type Storage = SparseSetStorage<Self>; }
+
+fn run(layout: Layout) {
+    let mut ecs = Ecs::new_with_layout(layout)
+        .with_storage::<Pos>()
+        .with_storage::<Vel>()
+        .with_storage::<Rare>();
+    for i in 0..10_000 {
+        let entity = ecs.spawn((Pos(0), Vel(i)));
+        if i % 100 == 0 {
+            ecs.insert_comp(entity, Rare);
+        }
+    }
+    // Entities without components
+    for _ in 0..10 {
+        ecs.create();
+    }
+
+    ecs.query::<(&mut Pos, &Vel)>().par_for_each(|(pos, vel)| pos.0 += vel.0);
+    ecs.query::<(MaybeMut<Pos>, &Vel)>().par_for_each(|(pos, vel)| pos.unwrap().0 += vel.0);
+    assert_eq!(ecs.query::<&Pos>().iter().map(|pos| pos.0).sum::<u64>(), 2 * (0..10_000).sum::<u64>());
+
+    let total = AtomicU64::new(0);
+    ecs.query::<(&Pos, With<Rare>)>().par_for_each(|(pos, ())| { total.fetch_add(pos.0, Ordering::Relaxed); });
+    assert_eq!(total.into_inner(), (0..100).map(|i| 2 * i * 100).sum::<u64>());
+
+    assert_eq!(ecs.query::<(EntityId, Maybe<Rare>)>().par_iter().filter(|(_, rare)| rare.is_some()).count(), 100);
+    assert_eq!(ecs.query::<EntityId>().par_iter().count(), 10_010);
+}
+
+#[test]
+fn par_iter_storage() { run(Layout::Storage) }
+
+#[test]
+fn par_iter_archetype() { run(Layout::Archetype) }
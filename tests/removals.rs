@@ -0,0 +1,54 @@
+use synco::*;
+
+struct Pos;
+impl Component for Pos {}
+
+fn watch(removed: RemovedComponents<Pos>) -> usize { removed.iter().count() }
+
+#[test]
+fn pruned_once_seen() {
+    let mut ecs = Ecs::new().with_storage::<Pos>();
+    let state = SystemState::new();
+    assert_eq!(ecs.run_with_state(&state, watch), 0);
+
+    for _ in 0..1000 {
+        let entity = ecs.create().with(Pos).finish();
+        ecs.delete(entity);
+        assert_eq!(ecs.run_with_state(&state, watch), 1);
+    }
+
+    // Without `maintain`, the log has still been emptied, so a new system sees nothing
+    assert_eq!(ecs.run(watch), 0);
+}
+
+#[test]
+fn kept_until_every_system_has_seen() {
+    let mut ecs = Ecs::new().with_storage::<Pos>();
+    let (watching, lagging) = (SystemState::new(), SystemState::new());
+    assert_eq!(ecs.run_with_state(&watching, watch), 0);
+    assert_eq!(ecs.run_with_state(&lagging, watch), 0);
+
+    for _ in 0..3 {
+        let entity = ecs.create().with(Pos).finish();
+        ecs.remove_comp::<Pos>(entity);
+        assert_eq!(ecs.run_with_state(&watching, watch), 1);
+    }
+
+    assert_eq!(ecs.run_with_state(&lagging, watch), 3);
+    assert_eq!(ecs.run_with_state(&lagging, watch), 0);
+    assert_eq!(ecs.run(watch), 0);
+}
+
+#[test]
+fn dropped_states_stop_keeping_removals() {
+    let mut ecs = Ecs::new().with_storage::<Pos>();
+    let lagging = SystemState::new();
+    assert_eq!(ecs.run_with_state(&lagging, watch), 0);
+
+    let entity = ecs.create().with(Pos).finish();
+    ecs.delete(entity);
+    assert_eq!(ecs.run(watch), 1);
+    drop(lagging);
+    assert_eq!(ecs.run(watch), 1);
+    assert_eq!(ecs.run(watch), 0);
+}
@@ -0,0 +1,125 @@
+use synco::*;
+
+#[derive(Debug, PartialEq)]
+struct Sparse(u32);
+impl Component for Sparse { type Storage = SparseSetStorage<Self>; }
+
+#[test]
+fn sparse_set_swap_remove() {
+    for layout in [Layout::Storage, Layout::Archetype] {
+        let mut ecs = Ecs::new_with_layout(layout).with_storage::<Sparse>();
+        let es = (0..3).map(|i| ecs.spawn((Sparse(i),))).collect::<Vec<_>>();
+
+        // The last component is moved into the removed one's slot
+        assert_eq!(ecs.remove_comp::<Sparse>(es[1]), Some(Sparse(1)));
+        let mut q = ecs.query::<&Sparse>();
+        assert_eq!(q.get(es[2]), Some(&Sparse(2)));
+        assert_eq!(q.get(es[0]), Some(&Sparse(0)));
+        assert_eq!(q.get(es[1]), None);
+        drop(q);
+
+        ecs.insert_comp(es[1], Sparse(3));
+        assert_eq!(ecs.remove_comp::<Sparse>(es[0]), Some(Sparse(0)));
+        let mut q = ecs.query::<(EntityId, &Sparse)>();
+        assert_eq!(q.get(es[1]), Some((es[1], &Sparse(3))));
+        assert_eq!(q.get(es[2]), Some((es[2], &Sparse(2))));
+        assert_eq!(q.iter().count(), 2);
+    }
+}
+
+#[test]
+fn sparse_set_dense() {
+    let mut storage = SparseSetStorage::<Sparse>::default();
+    let mut entities = Entities::default();
+    let es = (0..3).map(|_| entities.create()).collect::<Vec<_>>();
+
+    unsafe {
+        for (i, entity) in es.iter().enumerate() {
+            storage.insert_unchecked(*entity, Sparse(i as u32));
+        }
+        assert_eq!(storage.remove_unchecked(es[0]), Sparse(0));
+        assert_eq!(*storage.get_unchecked(es[2]), Sparse(2));
+    }
+    assert_eq!(storage.dense(), Some(&[es[2], es[1]][..]));
+    assert_eq!(storage.iter().map(|(entity, item)| (entity, item.0)).collect::<Vec<_>>(), [(es[2], 2), (es[1], 1)]);
+}
+
+#[derive(Debug, PartialEq)]
+struct Ordered(u32);
+impl Component for Ordered { type Storage = BTreeStorage<Self>; }
+
+#[test]
+fn btree_iter_in_order() {
+    let mut ecs = Ecs::new().with_storage::<Ordered>();
+    let es = (0..5).map(|_| ecs.create().finish()).collect::<Vec<_>>();
+    for i in [4, 2, 0, 3, 1] {
+        ecs.insert_comp(es[i], Ordered(i as u32));
+    }
+    ecs.remove_comp::<Ordered>(es[3]);
+
+    let storage = ecs.read_resource::<ComponentStorage<Ordered>>();
+    assert_eq!(
+        storage.iter().map(|(entity, item)| (entity, item.0)).collect::<Vec<_>>(),
+        [(es[0], 0), (es[1], 1), (es[2], 2), (es[4], 4)],
+    );
+}
+
+struct Other(u32);
+impl Component for Other {}
+
+macro_rules! query_storage {
+    ($($test:ident: $storage:ident),*) => {$(
+        #[test]
+        fn $test() {
+            struct Marked;
+            impl Component for Marked { type Storage = $storage<Self>; }
+
+            for layout in [Layout::Storage, Layout::Archetype] {
+                let mut ecs = Ecs::new_with_layout(layout)
+                    .with_storage::<Marked>()
+                    .with_storage::<Other>();
+                let es = (0..100).map(|i| ecs.spawn((Other(i),))).collect::<Vec<_>>();
+                for entity in es.iter().step_by(3) {
+                    ecs.insert_comp(*entity, Marked);
+                }
+                ecs.delete(es[3]);
+                ecs.remove_comp::<Marked>(es[6]);
+
+                ecs.query::<(&Marked, &mut Other)>().iter().for_each(|(_, other)| other.0 += 1000);
+                let marked = |i: u32| i % 3 == 0 && i != 3 && i != 6;
+                assert_eq!(ecs.query::<&Marked>().iter().count(), 32);
+                assert_eq!(ecs.query::<(Not<Marked>, &Other)>().iter().count(), 67);
+                assert!(ecs.query::<(Maybe<Marked>, &Other)>()
+                    .iter()
+                    .all(|(m, other)| m.is_some() == (other.0 >= 1000) && m.is_some() == marked(other.0 % 1000)));
+                assert!(ecs.query::<&Marked>().get(es[9]).is_some());
+                assert!(ecs.query::<&Marked>().get(es[6]).is_none());
+            }
+        }
+    )*};
+}
+
+query_storage!(
+    query_vec: VecStorage,
+    query_sparse_set: SparseSetStorage,
+    query_hash_map: HashMapStorage,
+    query_btree: BTreeStorage,
+    query_null: NullStorage
+);
+
+#[test]
+fn debug_while_borrowed() {
+    for layout in [Layout::Storage, Layout::Archetype] {
+        let mut ecs = Ecs::new_with_layout(layout)
+            .with_storage::<Sparse>()
+            .with_storage::<Other>();
+        ecs.spawn((Sparse(0), Other(0)));
+        ecs.spawn((Other(1),));
+
+        let name = core::any::type_name::<Other>();
+        assert!(format!("{:?}", ecs).contains(&format!("({:?}, 2)", name)));
+        let q = ecs.query::<&mut Other>();
+        assert!(format!("{:?}", ecs).contains(&format!("({:?}, <borrowed>)", name)));
+        drop(q);
+    }
+}
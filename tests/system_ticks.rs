@@ -0,0 +1,71 @@
+use synco::*;
+
+struct Pos;
+impl Component for Pos {}
+
+fn counter() -> impl Fn(Query<Added<Pos>>) -> usize + Clone {
+    |mut q: Query<Added<Pos>>| q.iter().count()
+}
+
+#[test]
+fn states_track_ticks_separately() {
+    let mut ecs = Ecs::new().with_storage::<Pos>();
+    ecs.create().with(Pos).finish();
+
+    // Two instances of the same closure type, each with its own state
+    let (a, a_state) = (counter(), SystemState::new());
+    let (b, b_state) = (counter(), SystemState::new());
+    assert_eq!(ecs.run_with_state(&a_state, a.clone()), 1);
+    // `b` has never run, so it must still see the insertion
+    assert_eq!(ecs.run_with_state(&b_state, b.clone()), 1);
+    assert_eq!(ecs.run_with_state(&a_state, a.clone()), 0);
+    assert_eq!(ecs.run_with_state(&b_state, b.clone()), 0);
+
+    ecs.create().with(Pos).finish();
+    assert_eq!(ecs.run_with_state(&b_state, b), 1);
+    assert_eq!(ecs.run_with_state(&a_state, a), 1);
+}
+
+#[test]
+fn run_without_state_sees_everything() {
+    let mut ecs = Ecs::new().with_storage::<Pos>();
+    ecs.create().with(Pos).finish();
+    ecs.create().with(Pos).finish();
+
+    let mut total = 0;
+    ecs.run(|mut q: Query<Added<Pos>>| for _ in q.iter() { total += 1 });
+    ecs.run(|mut q: Query<Added<Pos>>| for _ in q.iter() { total += 1 });
+    assert_eq!(total, 4);
+}
+
+#[derive(Clone)]
+struct CountAdded(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+impl<'a> System<'a> for CountAdded {
+    type Input = Query<'a, Added<Pos>>;
+
+    fn run(self, mut q: Self::Input) {
+        self.0.fetch_add(q.iter().count(), std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn dispatcher_tracks_ticks_per_system() {
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+    let mut ecs = Ecs::new().with_storage::<Pos>();
+    ecs.create().with(Pos).finish();
+
+    let seen = Arc::new(AtomicUsize::new(0));
+    let dispatcher = Dispatcher::new()
+        .with(CountAdded(seen.clone()))
+        .with(CountAdded(seen.clone()));
+
+    dispatcher.run(&ecs);
+    assert_eq!(seen.load(Ordering::Relaxed), 2);
+    dispatcher.run(&ecs);
+    assert_eq!(seen.load(Ordering::Relaxed), 2);
+    ecs.create().with(Pos).finish();
+    dispatcher.run(&ecs);
+    assert_eq!(seen.load(Ordering::Relaxed), 4);
+}